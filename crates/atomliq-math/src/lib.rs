@@ -0,0 +1,669 @@
+//! Fixed-point valuation and liquidation math for atomliq.
+//!
+//! Shared by the on-chain program and off-chain keepers so both sides compute
+//! identical values. Everything is checked integer arithmetic: no floats, no
+//! allocation, no `std`.
+//!
+//! Every conversion that can lose precision takes an explicit [`Rounding`].
+//! Callers pick the direction that favors the protocol: collateral and seized
+//! amounts round down, debt and amounts owed to the protocol round up.
+
+#![no_std]
+
+use core::fmt;
+
+/// Scale of [`Fixed`]: 6 decimals.
+pub const PRECISION: u128 = 1_000_000;
+/// Decimal places of [`PRECISION`].
+pub const PRECISION_DECIMALS: i32 = 6;
+/// Most mint decimals a token amount may have.
+pub const MAX_TOKEN_DECIMALS: u8 = 18;
+/// Denominator of basis-point ratios.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+/// Smallest oracle exponent accepted by [`Price::new`].
+pub const MIN_PRICE_EXPONENT: i32 = -18;
+/// Largest oracle exponent accepted by [`Price::new`].
+pub const MAX_PRICE_EXPONENT: i32 = 8;
+
+/// Why a calculation could not produce a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathError {
+    /// An intermediate or final result does not fit its type.
+    Overflow,
+    /// Division by a zero fixed-point value.
+    DivisionByZero,
+    /// Prices must be strictly positive.
+    NonPositivePrice,
+    /// Price exponent outside `MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT`.
+    InvalidExponent,
+    /// Mint decimals above `MAX_TOKEN_DECIMALS`.
+    InvalidDecimals,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            MathError::Overflow => "math overflow",
+            MathError::DivisionByZero => "division by zero",
+            MathError::NonPositivePrice => "price must be positive",
+            MathError::InvalidExponent => "price exponent out of range",
+            MathError::InvalidDecimals => "token decimals out of range",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// Result of a checked calculation.
+pub type MathResult<T> = core::result::Result<T, MathError>;
+
+/// Direction to round a result that does not divide evenly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Toward zero: for values paid out by the protocol.
+    Down,
+    /// Away from zero: for values owed to the protocol.
+    Up,
+}
+
+/// `value × numerator ÷ denominator`, rounded as requested.
+pub fn mul_div(value: u128, numerator: u128, denominator: u128, rounding: Rounding) -> MathResult<u128> {
+    let product = value.checked_mul(numerator).ok_or(MathError::Overflow)?;
+    div(product, denominator, rounding)
+}
+
+/// `numerator ÷ denominator`, rounded as requested.
+pub fn div(numerator: u128, denominator: u128, rounding: Rounding) -> MathResult<u128> {
+    if denominator == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    Ok(match rounding {
+        Rounding::Down => numerator / denominator,
+        Rounding::Up => numerator.div_ceil(denominator),
+    })
+}
+
+/// Narrow to `u64`, failing instead of truncating.
+pub fn to_u64(value: u128) -> MathResult<u64> {
+    u64::try_from(value).map_err(|_| MathError::Overflow)
+}
+
+/// Unsigned fixed-point number with [`PRECISION`] (6 decimal) scale.
+///
+/// USD values, limits and health factors are all `Fixed`; `Fixed::ONE` is $1
+/// or a health factor of 1.0. Multiplication and division round down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed(u128);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(PRECISION);
+    pub const MAX: Fixed = Fixed(u128::MAX);
+
+    /// Wrap a value already scaled by [`PRECISION`].
+    pub const fn from_raw(raw: u128) -> Self {
+        Fixed(raw)
+    }
+
+    /// The underlying value scaled by [`PRECISION`].
+    pub const fn raw(self) -> u128 {
+        self.0
+    }
+
+    /// A whole number; cannot overflow since `u64::MAX * PRECISION < u128::MAX`.
+    pub const fn from_integer(value: u64) -> Self {
+        Fixed(value as u128 * PRECISION)
+    }
+
+    /// A basis-point ratio, e.g. `from_bps(9500)` is 0.95.
+    pub const fn from_bps(bps: u16) -> Self {
+        Fixed(bps as u128 * PRECISION / BPS_DENOMINATOR)
+    }
+
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Fixed) -> MathResult<Fixed> {
+        self.0.checked_add(other.0).map(Fixed).ok_or(MathError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Fixed) -> MathResult<Fixed> {
+        self.0.checked_sub(other.0).map(Fixed).ok_or(MathError::Overflow)
+    }
+
+    pub fn saturating_sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+
+    /// `self × other`, rounded down.
+    pub fn checked_mul(self, other: Fixed) -> MathResult<Fixed> {
+        mul_div(self.0, other.0, PRECISION, Rounding::Down).map(Fixed)
+    }
+
+    /// `self ÷ other`, rounded down.
+    pub fn checked_div(self, other: Fixed) -> MathResult<Fixed> {
+        mul_div(self.0, PRECISION, other.0, Rounding::Down).map(Fixed)
+    }
+
+    /// `self × bps / 10000`, rounded down.
+    pub fn mul_bps(self, bps: u16) -> MathResult<Fixed> {
+        apply_bps(self.0, bps).map(Fixed)
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:06}", self.0 / PRECISION, self.0 % PRECISION)
+    }
+}
+
+/// Oracle price of one whole token, `mantissa × 10^exponent`, known to be positive and in range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Price {
+    mantissa: u64,
+    exponent: i32,
+}
+
+impl Price {
+    /// Validate a raw oracle price (e.g. Pyth `price` and `exponent`).
+    pub fn new(mantissa: i64, exponent: i32) -> MathResult<Price> {
+        if mantissa <= 0 {
+            return Err(MathError::NonPositivePrice);
+        }
+        if !(MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT).contains(&exponent) {
+            return Err(MathError::InvalidExponent);
+        }
+        Ok(Price {
+            mantissa: mantissa as u64,
+            exponent,
+        })
+    }
+
+    pub const fn mantissa(self) -> u64 {
+        self.mantissa
+    }
+
+    pub const fn exponent(self) -> i32 {
+        self.exponent
+    }
+}
+
+// 10^exp, failing past 10^38
+fn pow10(exp: u32) -> MathResult<u128> {
+    10u128.checked_pow(exp).ok_or(MathError::Overflow)
+}
+
+// Power of ten that turns `amount × mantissa` of a token with `decimals` into a Fixed value:
+// amount / 10^decimals × mantissa × 10^exponent × PRECISION
+fn value_exponent(decimals: u8, price: Price) -> MathResult<i32> {
+    if decimals > MAX_TOKEN_DECIMALS {
+        return Err(MathError::InvalidDecimals);
+    }
+    Ok(price.exponent + PRECISION_DECIMALS - decimals as i32)
+}
+
+// value × 10^exp ÷ divisor, exact even when value × 10^exp alone would overflow.
+// Long division in steps of 10^12: the remainder is below the divisor (< 2^64 here),
+// so remainder × 10^12 always fits
+fn mul_pow10_div(value: u128, exp: u32, divisor: u128, rounding: Rounding) -> MathResult<u128> {
+    const STEP: u32 = 12;
+    if divisor == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+
+    let mut quotient = value / divisor;
+    let mut remainder = value % divisor;
+    let mut left = exp;
+    while left > 0 {
+        let step = left.min(STEP);
+        let scale = pow10(step)?;
+        let scaled_remainder = remainder.checked_mul(scale).ok_or(MathError::Overflow)?;
+        quotient = quotient
+            .checked_mul(scale)
+            .and_then(|q| q.checked_add(scaled_remainder / divisor))
+            .ok_or(MathError::Overflow)?;
+        remainder = scaled_remainder % divisor;
+        left -= step;
+    }
+
+    if rounding == Rounding::Up && remainder > 0 {
+        quotient = quotient.checked_add(1).ok_or(MathError::Overflow)?;
+    }
+    Ok(quotient)
+}
+
+/// `value × bps / 10000`, rounded down.
+pub fn apply_bps(value: u128, bps: u16) -> MathResult<u128> {
+    mul_div(value, bps as u128, BPS_DENOMINATOR, Rounding::Down)
+}
+
+/// Value of `amount` base units of a token with `decimals` at `price`:
+/// `amount / 10^decimals × mantissa × 10^exponent`.
+pub fn token_value(amount: u64, decimals: u8, price: Price, rounding: Rounding) -> MathResult<Fixed> {
+    // u64 × (i64 > 0) never overflows a u128
+    let product = amount as u128 * price.mantissa as u128;
+    let exponent = value_exponent(decimals, price)?;
+
+    let value = if exponent < 0 {
+        div(product, pow10(exponent.unsigned_abs())?, rounding)?
+    } else {
+        product
+            .checked_mul(pow10(exponent.unsigned_abs())?)
+            .ok_or(MathError::Overflow)?
+    };
+
+    Ok(Fixed(value))
+}
+
+/// Base units of a token with `decimals` worth `value` at `price`.
+pub fn token_amount(value: Fixed, decimals: u8, price: Price, rounding: Rounding) -> MathResult<u64> {
+    let exponent = value_exponent(decimals, price)?;
+    let amount = if exponent < 0 {
+        mul_pow10_div(value.0, exponent.unsigned_abs(), price.mantissa as u128, rounding)?
+    } else {
+        // exponent <= 14 and mantissa < 2^63, so the divisor always fits
+        div(value.0, price.mantissa as u128 * pow10(exponent.unsigned_abs())?, rounding)?
+    };
+
+    to_u64(amount)
+}
+
+/// Health factor `liquidation_limit / debt_value`, rounded down; [`Fixed::MAX`] without debt.
+pub fn health_factor(liquidation_limit: Fixed, debt_value: Fixed) -> MathResult<Fixed> {
+    if debt_value.is_zero() {
+        return Ok(Fixed::MAX);
+    }
+    liquidation_limit.checked_div(debt_value)
+}
+
+/// Liquidation bonus scaled with how far health has fallen below 1.0.
+///
+/// Dutch-auction style: every basis point of shortfall adds a basis point of
+/// bonus on top of `base_bonus_bps`, capped at `max_bonus_bps`.
+pub fn liquidation_bonus_bps(
+    base_bonus_bps: u16,
+    health_factor: Fixed,
+    max_bonus_bps: u16,
+) -> MathResult<u16> {
+    let shortfall_bps = Fixed::ONE
+        .saturating_sub(health_factor)
+        .0
+        .checked_mul(BPS_DENOMINATOR)
+        .ok_or(MathError::Overflow)?
+        / PRECISION;
+
+    let bonus_bps = (base_bonus_bps as u128)
+        .checked_add(shortfall_bps)
+        .ok_or(MathError::Overflow)?
+        .min(max_bonus_bps as u128);
+
+    // At most max_bonus_bps, so it fits a u16
+    Ok(bonus_bps as u16)
+}
+
+/// Collateral value a liquidator receives for repaying `debt_value`: `debt × (1 + bonus)`, rounded down.
+pub fn seize_value(debt_value: Fixed, bonus_bps: u16) -> MathResult<Fixed> {
+    mul_div(
+        debt_value.0,
+        BPS_DENOMINATOR + bonus_bps as u128,
+        BPS_DENOMINATOR,
+        Rounding::Down,
+    )
+    .map(Fixed)
+}
+
+/// Debt value that seizing `collateral_value` pays for: `collateral / (1 + bonus)`, rounded up.
+pub fn repay_value_for_seize(collateral_value: Fixed, bonus_bps: u16) -> MathResult<Fixed> {
+    mul_div(
+        collateral_value.0,
+        BPS_DENOMINATOR,
+        BPS_DENOMINATOR + bonus_bps as u128,
+        Rounding::Up,
+    )
+    .map(Fixed)
+}
+
+/// Token amounts moved by one liquidation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidationAmounts {
+    /// Debt tokens the liquidator repays.
+    pub repay: u64,
+    /// Collateral tokens the liquidator receives, bonus included.
+    pub seize: u64,
+    /// Whether `seize` is all of the available collateral.
+    pub collateral_exhausted: bool,
+}
+
+/// Repay and seize amounts for repaying up to `max_repay` against `collateral` base units.
+///
+/// Prices are mid prices and each token is valued with its own decimals. The
+/// seized amount rounds down and, when the collateral runs out, the reduced
+/// repayment rounds up, so rounding never hands the liquidator more than the bonus.
+pub fn liquidation_amounts(
+    max_repay: u64,
+    repay_decimals: u8,
+    repay_price: Price,
+    collateral: u64,
+    collateral_decimals: u8,
+    collateral_price: Price,
+    bonus_bps: u16,
+) -> MathResult<LiquidationAmounts> {
+    let repay_value = token_value(max_repay, repay_decimals, repay_price, Rounding::Down)?;
+    let seize = seize_value(repay_value, bonus_bps).and_then(|value| {
+        token_amount(value, collateral_decimals, collateral_price, Rounding::Down)
+    });
+    match seize {
+        Ok(seize) if seize <= collateral => {
+            return Ok(LiquidationAmounts {
+                repay: max_repay,
+                seize,
+                collateral_exhausted: false,
+            });
+        }
+        // More than a u64 of collateral is certainly more than the obligation holds
+        Ok(_) | Err(MathError::Overflow) => {}
+        Err(err) => return Err(err),
+    }
+
+    // Seize everything; the liquidator repays the debt it covers at the bonus
+    let collateral_value = token_value(collateral, collateral_decimals, collateral_price, Rounding::Up)?;
+    let covered_value = repay_value_for_seize(collateral_value, bonus_bps)?;
+    let repay = token_amount(covered_value, repay_decimals, repay_price, Rounding::Up)?.min(max_repay);
+
+    Ok(LiquidationAmounts {
+        repay,
+        seize: collateral,
+        collateral_exhausted: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(mantissa: i64, exponent: i32) -> Price {
+        Price::new(mantissa, exponent).unwrap()
+    }
+
+    #[test]
+    fn price_rejects_non_positive_mantissa() {
+        assert_eq!(Price::new(0, -8), Err(MathError::NonPositivePrice));
+        assert_eq!(Price::new(-1, -8), Err(MathError::NonPositivePrice));
+    }
+
+    #[test]
+    fn price_accepts_exactly_the_exponent_range() {
+        for exponent in -30..=30 {
+            let in_range = (MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT).contains(&exponent);
+            assert_eq!(Price::new(1, exponent).is_ok(), in_range, "exponent {exponent}");
+        }
+    }
+
+    #[test]
+    fn token_value_of_one_unit_for_every_exponent() {
+        // 1 unit at price 1 × 10^e is worth 10^e, i.e. 10^(e + 6) raw
+        for exponent in MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT {
+            let value = token_value(1, 0, price(1, exponent), Rounding::Down).unwrap();
+            let expected = if exponent + 6 >= 0 {
+                10u128.pow((exponent + 6) as u32)
+            } else {
+                0
+            };
+            assert_eq!(value.raw(), expected, "exponent {exponent}");
+        }
+    }
+
+    #[test]
+    fn token_value_matches_known_prices() {
+        // 10 SOL (9 decimals) at 150.12345678 (mantissa 15012345678, exponent -8)
+        let sol = token_value(10_000_000_000, 9, price(15_012_345_678, -8), Rounding::Down).unwrap();
+        assert_eq!(sol, Fixed::from_raw(1_501_234_567));
+
+        // 1500 USDC (6 decimals) at 1.0001 (mantissa 10001, exponent -4)
+        let usdc = token_value(1_500_000_000, 6, price(10_001, -4), Rounding::Down).unwrap();
+        assert_eq!(usdc, Fixed::from_raw(1_500_150_000));
+
+        // Positive exponent: 3 units at 5 × 10^2
+        assert_eq!(token_value(3, 0, price(5, 2), Rounding::Down).unwrap(), Fixed::from_integer(1500));
+    }
+
+    #[test]
+    fn one_whole_token_is_worth_its_price_for_any_decimals() {
+        for decimals in 0..=MAX_TOKEN_DECIMALS {
+            let one = 10u64.pow(decimals as u32);
+            let value = token_value(one, decimals, price(2_500, -2), Rounding::Down).unwrap();
+            assert_eq!(value, Fixed::from_integer(25), "decimals {decimals}");
+            assert_eq!(token_amount(value, decimals, price(2_500, -2), Rounding::Down).unwrap(), one);
+        }
+    }
+
+    #[test]
+    fn decimals_above_the_maximum_are_rejected() {
+        let p = price(1, 0);
+        assert_eq!(token_value(1, MAX_TOKEN_DECIMALS + 1, p, Rounding::Down), Err(MathError::InvalidDecimals));
+        assert_eq!(token_amount(Fixed::ONE, MAX_TOKEN_DECIMALS + 1, p, Rounding::Down), Err(MathError::InvalidDecimals));
+    }
+
+    #[test]
+    fn token_amount_avoids_intermediate_overflow() {
+        // $1M of an 18-decimal token priced with exponent -18 needs 10^30 × the value
+        // before dividing, far past u128, yet the answer (10^24 base units) only overflows u64
+        let p = price(1_000_000_000_000_000_000, -18);
+        assert_eq!(
+            token_amount(Fixed::from_integer(1_000_000), 18, p, Rounding::Down),
+            Err(MathError::Overflow)
+        );
+        // $10 is 10^19 base units: still over u64::MAX ≈ 1.8 × 10^19, $1 fits
+        assert_eq!(
+            token_amount(Fixed::from_integer(1), 18, p, Rounding::Down).unwrap(),
+            1_000_000_000_000_000_000
+        );
+        // 1.5 units of a $3 token at 12 decimals, rounding both ways
+        let p = price(3, 0);
+        let value = Fixed::from_raw(4_500_001);
+        assert_eq!(token_amount(value, 12, p, Rounding::Down).unwrap(), 1_500_000_333_333);
+        assert_eq!(token_amount(value, 12, p, Rounding::Up).unwrap(), 1_500_000_333_334);
+    }
+
+    #[test]
+    fn token_amount_round_trips_exact_values_for_every_exponent() {
+        for exponent in MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT {
+            let p = price(7, exponent);
+            let value = token_value(1_000, 0, p, Rounding::Down).unwrap();
+            let amount = token_amount(value, 0, p, Rounding::Down).unwrap();
+            if exponent >= -6 {
+                // No precision lost on the way in, so the way back is exact
+                assert_eq!(amount, 1_000, "exponent {exponent}");
+            } else {
+                assert!(amount <= 1_000, "exponent {exponent}");
+            }
+        }
+    }
+
+    #[test]
+    fn token_amount_rounds_in_the_requested_direction() {
+        // $1.999999 at $1 is 1 unit rounded down and 2 rounded up
+        let value = Fixed::from_raw(1_999_999);
+        assert_eq!(token_amount(value, 0, price(1, 0), Rounding::Down).unwrap(), 1);
+        assert_eq!(token_amount(value, 0, price(1, 0), Rounding::Up).unwrap(), 2);
+        // Exact amounts do not move
+        let exact = Fixed::from_integer(2);
+        assert_eq!(token_amount(exact, 0, price(1, 0), Rounding::Up).unwrap(), 2);
+        // Positive exponent: $1501 at $500 per unit
+        let value = Fixed::from_integer(1501);
+        assert_eq!(token_amount(value, 0, price(5, 2), Rounding::Down).unwrap(), 3);
+        assert_eq!(token_amount(value, 0, price(5, 2), Rounding::Up).unwrap(), 4);
+    }
+
+    #[test]
+    fn token_value_rounds_in_the_requested_direction() {
+        // 1 unit at 10^-8 is worth 10^-8, below the smallest Fixed step
+        assert_eq!(token_value(1, 0, price(1, -8), Rounding::Down).unwrap(), Fixed::ZERO);
+        assert_eq!(token_value(1, 0, price(1, -8), Rounding::Up).unwrap(), Fixed::from_raw(1));
+        assert_eq!(token_value(100, 0, price(1, -8), Rounding::Up).unwrap(), Fixed::from_raw(1));
+    }
+
+    #[test]
+    fn mul_div_rounds_and_checks() {
+        assert_eq!(mul_div(10, 2, 3, Rounding::Down).unwrap(), 6);
+        assert_eq!(mul_div(10, 2, 3, Rounding::Up).unwrap(), 7);
+        assert_eq!(mul_div(9, 2, 3, Rounding::Up).unwrap(), 6);
+        assert_eq!(mul_div(1, 1, 0, Rounding::Down), Err(MathError::DivisionByZero));
+        assert_eq!(mul_div(u128::MAX, 2, 2, Rounding::Down), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn to_u64_never_truncates() {
+        assert_eq!(to_u64(u64::MAX as u128).unwrap(), u64::MAX);
+        assert_eq!(to_u64(u64::MAX as u128 + 1), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn overflow_is_an_error_not_a_panic() {
+        assert_eq!(
+            token_value(u64::MAX, 0, price(i64::MAX, MAX_PRICE_EXPONENT), Rounding::Down),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(
+            token_amount(Fixed::MAX, 0, price(1, MIN_PRICE_EXPONENT), Rounding::Down),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(Fixed::MAX.checked_add(Fixed::ONE), Err(MathError::Overflow));
+        assert_eq!(Fixed::ZERO.checked_sub(Fixed::ONE), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn fixed_arithmetic() {
+        let half = Fixed::from_bps(5000);
+        assert_eq!(half, Fixed::from_raw(500_000));
+        assert_eq!(Fixed::from_integer(3).checked_mul(half).unwrap(), Fixed::from_raw(1_500_000));
+        assert_eq!(Fixed::ONE.checked_div(Fixed::from_integer(3)).unwrap(), Fixed::from_raw(333_333));
+        assert_eq!(Fixed::ONE.checked_div(Fixed::ZERO), Err(MathError::DivisionByZero));
+        assert_eq!(Fixed::from_integer(10).mul_bps(7500).unwrap(), Fixed::from_raw(7_500_000));
+    }
+
+    #[test]
+    fn health_factor_without_debt_is_max() {
+        assert_eq!(health_factor(Fixed::from_integer(5), Fixed::ZERO).unwrap(), Fixed::MAX);
+    }
+
+    #[test]
+    fn health_factor_is_limit_over_debt() {
+        let hf = health_factor(Fixed::from_integer(90), Fixed::from_integer(100)).unwrap();
+        assert_eq!(hf, Fixed::from_bps(9000));
+    }
+
+    #[test]
+    fn liquidation_bonus_scales_with_shortfall_and_caps() {
+        // Healthy or at 1.0: base bonus only
+        assert_eq!(liquidation_bonus_bps(500, Fixed::ONE, 1500).unwrap(), 500);
+        assert_eq!(liquidation_bonus_bps(500, Fixed::from_integer(2), 1500).unwrap(), 500);
+        // HF 0.97: 300 bps of shortfall
+        assert_eq!(liquidation_bonus_bps(500, Fixed::from_bps(9700), 1500).unwrap(), 800);
+        // HF 0.5: capped
+        assert_eq!(liquidation_bonus_bps(500, Fixed::from_bps(5000), 1500).unwrap(), 1500);
+    }
+
+    #[test]
+    fn seize_and_repay_values_are_inverse() {
+        let debt = Fixed::from_integer(100);
+        let seized = seize_value(debt, 500).unwrap();
+        assert_eq!(seized, Fixed::from_integer(105));
+        assert_eq!(repay_value_for_seize(seized, 500).unwrap(), debt);
+    }
+
+    #[test]
+    fn seize_value_rounds_down_and_repay_value_rounds_up() {
+        // 0.000019 × 1.05 = 0.00001995
+        assert_eq!(seize_value(Fixed::from_raw(19), 500).unwrap(), Fixed::from_raw(19));
+        // 0.000020 / 1.05 = 0.00001904...
+        assert_eq!(repay_value_for_seize(Fixed::from_raw(20), 500).unwrap(), Fixed::from_raw(20));
+        assert_eq!(repay_value_for_seize(Fixed::from_raw(1), 500).unwrap(), Fixed::from_raw(1));
+    }
+
+    #[test]
+    fn liquidation_seize_amount_rounds_down() {
+        // Repay 1001 units at $1 against collateral at $3 with a 5% bonus:
+        // $1051.05 of collateral is 350.35 units, of which the liquidator gets 350
+        let amounts = liquidation_amounts(1001, 0, price(1, 0), 1_000, 0, price(3, 0), 500).unwrap();
+        assert_eq!(
+            amounts,
+            LiquidationAmounts {
+                repay: 1001,
+                seize: 350,
+                collateral_exhausted: false,
+            }
+        );
+    }
+
+    #[test]
+    fn liquidation_repaid_debt_rounds_up_when_collateral_runs_out() {
+        // 100 units at $3 cover $285.714285... of debt at a 5% bonus: the liquidator repays 286
+        let amounts = liquidation_amounts(1_000, 0, price(1, 0), 100, 0, price(3, 0), 500).unwrap();
+        assert_eq!(
+            amounts,
+            LiquidationAmounts {
+                repay: 286,
+                seize: 100,
+                collateral_exhausted: true,
+            }
+        );
+    }
+
+    #[test]
+    fn liquidation_normalizes_decimals_across_mints() {
+        // Repay 1500 USDC (6 decimals, $1) against SOL (9 decimals, $150) with a 5% bonus:
+        // $1575 of collateral is 10.5 SOL
+        let amounts = liquidation_amounts(
+            1_500_000_000,
+            6,
+            price(100_000_000, -8),
+            20_000_000_000,
+            9,
+            price(15_000_000_000, -8),
+            500,
+        )
+        .unwrap();
+        assert_eq!(amounts.seize, 10_500_000_000);
+        assert_eq!(amounts.repay, 1_500_000_000);
+    }
+
+    #[test]
+    fn liquidation_never_seizes_dust_for_free() {
+        // 1 unit of collateral worth 10^-8 still costs a whole debt unit
+        let amounts = liquidation_amounts(10, 0, price(1, 0), 1, 0, price(1, -8), 500).unwrap();
+        assert_eq!(amounts.seize, 1);
+        assert_eq!(amounts.repay, 1);
+    }
+
+    #[test]
+    fn liquidation_repayment_never_exceeds_the_request() {
+        // Collateral worth barely more than the request: repay stays capped at the request
+        let amounts = liquidation_amounts(100, 0, price(1, 0), 105, 0, price(1, 0), 500).unwrap();
+        assert_eq!(amounts.repay, 100);
+        assert_eq!(amounts.seize, 105);
+    }
+
+    #[test]
+    fn fixed_displays_six_decimals() {
+        let mut buf = [0u8; 32];
+        let mut cursor = Cursor { buf: &mut buf, len: 0 };
+        fmt::write(&mut cursor, format_args!("{}", Fixed::from_raw(1_500_001))).unwrap();
+        assert_eq!(&cursor.buf[..cursor.len], b"1.500001");
+    }
+
+    // Minimal fmt::Write sink so the test stays no_std
+    struct Cursor<'a> {
+        buf: &'a mut [u8],
+        len: usize,
+    }
+
+    impl fmt::Write for Cursor<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.buf.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use atomliq_math::{
+    health_factor, liquidation_amounts, liquidation_bonus_bps, repay_value_for_seize,
+    seize_value, token_amount, token_value, Fixed, MathError, Price, Rounding,
+    MAX_PRICE_EXPONENT, MAX_TOKEN_DECIMALS, MIN_PRICE_EXPONENT,
+};
+use proptest::prelude::*;
+
+fn exponent() -> impl Strategy<Value = i32> {
+    MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT
+}
+
+fn decimals() -> impl Strategy<Value = u8> {
+    0..=MAX_TOKEN_DECIMALS
+}
+
+// Prices from 10^-18 to ~10^8 × 2^40 — wide enough to hit overflow at the top end
+fn price() -> impl Strategy<Value = Price> {
+    (1i64..=1 << 40, exponent()).prop_map(|(mantissa, exponent)| Price::new(mantissa, exponent).unwrap())
+}
+
+proptest! {
+    #[test]
+    fn never_panics_on_any_input(amount in any::<u64>(), decimals in any::<u8>(), mantissa in 1i64.., exponent in exponent(), raw in any::<u128>()) {
+        let price = Price::new(mantissa, exponent).unwrap();
+        for rounding in [Rounding::Down, Rounding::Up] {
+            let _ = token_value(amount, decimals, price, rounding);
+            let _ = token_amount(Fixed::from_raw(raw), decimals, price, rounding);
+        }
+    }
+
+    #[test]
+    fn round_trip_never_creates_tokens(amount in any::<u64>(), decimals in decimals(), price in price()) {
+        if let Ok(value) = token_value(amount, decimals, price, Rounding::Down) {
+            prop_assert!(token_amount(value, decimals, price, Rounding::Down).unwrap() <= amount);
+        }
+    }
+
+    #[test]
+    fn round_trip_is_exact_without_sub_unit_loss(amount in 0u64..1 << 40, mantissa in 1i64..1 << 20, exponent in -6i32..=MAX_PRICE_EXPONENT) {
+        let price = Price::new(mantissa, exponent).unwrap();
+        let value = token_value(amount, 0, price, Rounding::Down).unwrap();
+        prop_assert_eq!(token_amount(value, 0, price, Rounding::Down).unwrap(), amount);
+    }
+
+    #[test]
+    fn token_value_is_monotonic(a in any::<u64>(), b in any::<u64>(), price in price()) {
+        let (low, high) = if a <= b { (a, b) } else { (b, a) };
+        if let (Ok(low_value), Ok(high_value)) = (token_value(low, 0, price, Rounding::Down), token_value(high, 0, price, Rounding::Down)) {
+            prop_assert!(low_value <= high_value);
+        }
+    }
+
+    #[test]
+    fn token_value_overflows_consistently(amount in any::<u64>(), decimals in decimals(), price in price()) {
+        // Either a value that converts back, or an explicit overflow
+        match token_value(amount, decimals, price, Rounding::Down) {
+            Ok(value) => prop_assert!(token_amount(value, decimals, price, Rounding::Down).is_ok()),
+            Err(err) => prop_assert_eq!(err, MathError::Overflow),
+        }
+    }
+
+    #[test]
+    fn health_is_at_least_one_iff_limit_covers_debt(limit in any::<u64>(), debt in 1u64..) {
+        let hf = health_factor(Fixed::from_integer(limit), Fixed::from_integer(debt)).unwrap();
+        prop_assert_eq!(hf >= Fixed::ONE, limit >= debt);
+    }
+
+    #[test]
+    fn bonus_stays_between_base_and_cap(base in 0u16..=10000, max in 0u16..=10000, raw_hf in 0u128..2_000_000) {
+        let bonus = liquidation_bonus_bps(base, Fixed::from_raw(raw_hf), max).unwrap();
+        prop_assert!(bonus <= max);
+        prop_assert!(bonus >= base.min(max));
+    }
+
+    #[test]
+    fn bonus_grows_as_health_falls(base in 0u16..=5000, max in 0u16..=10000, a in 0u128..1_000_000, b in 0u128..1_000_000) {
+        let (low, high) = if a <= b { (a, b) } else { (b, a) };
+        let bonus_low_hf = liquidation_bonus_bps(base, Fixed::from_raw(low), max).unwrap();
+        let bonus_high_hf = liquidation_bonus_bps(base, Fixed::from_raw(high), max).unwrap();
+        prop_assert!(bonus_low_hf >= bonus_high_hf);
+    }
+
+    #[test]
+    fn seize_covers_debt_and_inverts(raw_debt in 0u128..1 << 100, bonus in 0u16..=10000) {
+        let debt = Fixed::from_raw(raw_debt);
+        let seized = seize_value(debt, bonus).unwrap();
+        prop_assert!(seized >= debt);
+        prop_assert!(repay_value_for_seize(seized, bonus).unwrap() <= debt);
+    }
+
+    #[test]
+    fn rounding_up_never_loses_value_and_adds_at_most_one_step(amount in any::<u64>(), price in price()) {
+        if let (Ok(down), Ok(up)) = (token_value(amount, 0, price, Rounding::Down), token_value(amount, 0, price, Rounding::Up)) {
+            prop_assert!(up >= down);
+            prop_assert!(up.raw() - down.raw() <= 1);
+        }
+    }
+
+    #[test]
+    fn round_trip_up_never_loses_tokens(amount in any::<u64>(), decimals in decimals(), price in price()) {
+        if let Ok(value) = token_value(amount, decimals, price, Rounding::Up) {
+            if let Ok(back) = token_amount(value, decimals, price, Rounding::Up) {
+                prop_assert!(back >= amount);
+            }
+        }
+    }
+
+    #[test]
+    fn liquidator_never_receives_more_than_the_bonus(
+        max_repay in 1u64..1 << 32,
+        collateral in 1u64..1 << 32,
+        (repay_mantissa, repay_exponent, repay_decimals) in (1i64..1 << 24, -8i32..=0, 0u8..=9),
+        (collateral_mantissa, collateral_exponent, collateral_decimals) in (1i64..1 << 24, -8i32..=0, 0u8..=9),
+        bonus in 0u16..=5000,
+    ) {
+        let repay_price = Price::new(repay_mantissa, repay_exponent).unwrap();
+        let collateral_price = Price::new(collateral_mantissa, collateral_exponent).unwrap();
+        let amounts = liquidation_amounts(
+            max_repay,
+            repay_decimals,
+            repay_price,
+            collateral,
+            collateral_decimals,
+            collateral_price,
+            bonus,
+        )
+        .unwrap();
+
+        prop_assert!(amounts.seize <= collateral);
+        prop_assert!(amounts.repay <= max_repay);
+        prop_assert!(amounts.repay > 0);
+        if !amounts.collateral_exhausted {
+            prop_assert_eq!(amounts.repay, max_repay);
+        }
+
+        // Exact: seize × collateral price ≤ repay × repay price × (1 + bonus), in whole tokens at a common 10^-17 scale
+        let scaled = |amount: u64, mantissa: i64, exponent: i32, decimals: u8| {
+            amount as u128 * mantissa as u128 * 10u128.pow((exponent + 8 + 9 - decimals as i32) as u32)
+        };
+        let seized = scaled(amounts.seize, collateral_mantissa, collateral_exponent, collateral_decimals) * 10_000;
+        let paid = scaled(amounts.repay, repay_mantissa, repay_exponent, repay_decimals) * (10_000 + bonus as u128);
+        prop_assert!(seized <= paid);
+    }
+}
@@ -1,295 +1,3282 @@
 use anchor_lang::prelude::*;
-use pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, get_feed_id_from_hex};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::Discriminator;
+use anchor_spl::token_interface::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+use anchor_spl::token_interface::{Burn, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked};
+use atomliq_math::{
+    mul_div, Fixed, MathError, Price, Rounding, MAX_PRICE_EXPONENT, MAX_TOKEN_DECIMALS,
+    MIN_PRICE_EXPONENT, PRECISION,
+};
+use pyth_solana_receiver_sdk::error::GetPriceError;
+use pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, VerificationLevel};
+use switchboard_on_demand::{PullFeedAccountData, SWITCHBOARD_ON_DEMAND_PROGRAM_ID};
 
 declare_id!("F3xVeUGPhuyMjtWf433WDAyhcA1SbQJjAsySw2d1Pf9G");
 
 // Constants for lending protocol
-const MAX_LIQUIDATION_INCENTIVE_BPS: u16 = 500; // 5% liquidation bonus
-const LIQUIDATION_THRESHOLD: u16 = 8000; // 80% LTV for liquidation
-const PRECISION: u128 = 1_000_000; // 6 decimal precision
+const DEFAULT_MAX_PRICE_AGE_SECS: u64 = 60; // Pull oracle updates usually land < 1s
+const DEFAULT_MAX_CONFIDENCE_BPS: u16 = 200; // Reject prices with conf wider than 2% of price
+const INDEX_PRECISION: u128 = 1_000_000_000_000_000_000; // 18 decimal precision for the borrow index
+const SECONDS_PER_YEAR: i64 = 31_536_000; // 365 days
+const MAX_RESERVES: usize = 8; // Reserves listed per pool
+const DEFAULT_CLOSE_FACTOR_BPS: u16 = 5000; // Max 50% of a borrow repaid per liquidation
+const DEFAULT_FULL_LIQUIDATION_HF_BPS: u16 = 9500; // Below HF 0.95 a borrow can be closed in full
+const DEFAULT_DUST_DEBT_VALUE: u64 = 10 * PRECISION as u64; // Debt value small enough to close in full
+const DEFAULT_MAX_LIQUIDATION_BONUS_BPS: u16 = 1500; // Cap on the scaled liquidation bonus
+const MAX_OBLIGATION_RESERVES: usize = 5; // Deposits (and separately borrows) per obligation
+const MAX_EMODE_CATEGORIES: usize = 4; // E-mode categories per pool
+
+// SPL stake pool program, which owns the stake pools behind LSTs such as JitoSOL
+const SPL_STAKE_POOL_PROGRAM_ID: Pubkey = pubkey!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+// Layout of the SPL StakePool account up to the fields read here:
+// account_type (1), manager, staker, stake_deposit_authority (3 x 32), withdraw bump (1),
+// validator_list, reserve_stake, pool_mint, manager_fee_account, token_program_id (5 x 32),
+// then total_lamports, pool_token_supply and last_update_epoch (u64 each)
+const STAKE_POOL_ACCOUNT_TYPE: u8 = 1;
+const STAKE_POOL_MINT_OFFSET: usize = 162;
+const STAKE_POOL_TOTAL_LAMPORTS_OFFSET: usize = 258;
+const STAKE_POOL_TOKEN_SUPPLY_OFFSET: usize = 266;
+const STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET: usize = 274;
+const STAKE_POOL_MIN_LEN: usize = 282;
+
+// Anchor discriminator of the swap program's `swap(amount_in, minimum_amount_out)`,
+// sha256("global:swap")[..8], called by liquidate_and_swap
+const SWAP_IX_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
 
 #[program]
 pub mod atomliq {
     use super::*;
 
-    /// Initialize a lending pool with configuration
-    pub fn initialize_pool(
-        ctx: Context<InitializePool>,
-        collateral_feed_id: String,
-        debt_feed_id: String,
-    ) -> Result<()> {
+    /// Initialize a lending pool with oracle configuration
+    pub fn initialize_pool(ctx: Context<InitializePool>) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         pool.authority = ctx.accounts.authority.key();
-        pool.collateral_feed_id = collateral_feed_id;
-        pool.debt_feed_id = debt_feed_id;
-        pool.liquidation_threshold = LIQUIDATION_THRESHOLD;
-        pool.liquidation_bonus_bps = MAX_LIQUIDATION_INCENTIVE_BPS;
+        pool.pending_authority = Pubkey::default();
+        pool.guardian = Pubkey::default();
+        pool.pause_flags = PauseFlags::default();
+        pool.swap_program = Pubkey::default();
+        pool.reserves = Vec::new();
+        pool.emode_categories = Vec::new();
+        pool.max_price_age_secs = DEFAULT_MAX_PRICE_AGE_SECS;
+        pool.max_confidence_bps = DEFAULT_MAX_CONFIDENCE_BPS;
+        pool.close_factor_bps = DEFAULT_CLOSE_FACTOR_BPS;
+        pool.full_liquidation_hf_bps = DEFAULT_FULL_LIQUIDATION_HF_BPS;
+        pool.dust_debt_value = DEFAULT_DUST_DEBT_VALUE;
+        pool.max_liquidation_bonus_bps = DEFAULT_MAX_LIQUIDATION_BONUS_BPS;
+
+        emit!(PoolInitialized {
+            pool: pool.key(),
+            authority: pool.authority,
+            max_price_age_secs: pool.max_price_age_secs,
+            max_confidence_bps: pool.max_confidence_bps,
+            close_factor_bps: pool.close_factor_bps,
+            full_liquidation_hf_bps: pool.full_liquidation_hf_bps,
+            max_liquidation_bonus_bps: pool.max_liquidation_bonus_bps,
+        });
 
         msg!("Lending pool initialized");
         Ok(())
     }
 
-    /// Initialize a user position
+    /// List a new asset in the pool as a reserve with its own vault, oracle and risk parameters
+    /// SPL Token and Token-2022 mints are supported; see validate_mint_extensions for which
+    /// mint extensions are accepted and which need `mint_risks` to opt in
+    pub fn add_reserve(
+        ctx: Context<AddReserve>,
+        oracle: OracleConfig,
+        config: ReserveConfig,
+        mint_risks: MintRiskOverrides,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        validate_oracle_config(&oracle)?;
+        validate_reserve_config(&config, &ctx.accounts.reserve.key(), pool)?;
+        validate_mint_extensions(&ctx.accounts.mint, &mint_risks)?;
+        require!(pool.reserves.len() < MAX_RESERVES, ErrorCode::TooManyReserves);
+        require!(
+            ctx.accounts.mint.decimals <= MAX_TOKEN_DECIMALS,
+            ErrorCode::UnsupportedMintDecimals
+        );
+        pool.reserves.push(ctx.accounts.reserve.key());
+
+        let clock = Clock::get()?;
+
+        let reserve = &mut ctx.accounts.reserve;
+        reserve.pool = pool.key();
+        reserve.mint = ctx.accounts.mint.key();
+        reserve.decimals = ctx.accounts.mint.decimals;
+        reserve.token_program = ctx.accounts.token_program.key();
+        reserve.vault = ctx.accounts.reserve_vault.key();
+        reserve.receipt_mint = ctx.accounts.receipt_mint.key();
+        reserve.oracle = oracle;
+        reserve.config = config;
+        reserve.borrow_index = INDEX_PRECISION;
+        reserve.supply_index = INDEX_PRECISION;
+        reserve.total_debt_shares = 0;
+        reserve.total_collateral = 0;
+        reserve.total_supply_shares = 0;
+        reserve.insurance_fund = 0;
+        reserve.protocol_fees = 0;
+        reserve.isolated_debt = 0;
+        reserve.last_accrual_ts = clock.unix_timestamp;
+        reserve.price = 0;
+        reserve.price_conf = 0;
+        reserve.price_exponent = 0;
+        reserve.last_update_slot = 0;
+        reserve.flash_loan_amount = 0;
+
+        emit!(ReserveAdded {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            mint: reserve.mint,
+            decimals: reserve.decimals,
+            token_program: reserve.token_program,
+            mint_risks,
+            oracle,
+            config,
+        });
+
+        msg!("Reserve added for mint {}", reserve.mint);
+        Ok(())
+    }
+
+    /// Update pool-wide oracle and liquidation parameters (authority only)
+    /// Remaining accounts, when raising the bonus cap: every reserve in the pool, in listing order
+    pub fn update_pool_config(ctx: Context<UpdatePool>, config: PoolConfig) -> Result<()> {
+        validate_pool_config(&config)?;
+
+        let pool = &mut ctx.accounts.pool;
+        let raises_bonus_cap = config.max_liquidation_bonus_bps > pool.max_liquidation_bonus_bps;
+        pool.max_price_age_secs = config.max_price_age_secs;
+        pool.max_confidence_bps = config.max_confidence_bps;
+        pool.close_factor_bps = config.close_factor_bps;
+        pool.full_liquidation_hf_bps = config.full_liquidation_hf_bps;
+        pool.dust_debt_value = config.dust_debt_value;
+        pool.max_liquidation_bonus_bps = config.max_liquidation_bonus_bps;
+
+        // A higher cap must stay solvent for every reserve and e-mode category already listed
+        if raises_bonus_cap {
+            require!(
+                ctx.remaining_accounts.len() == pool.reserves.len(),
+                ErrorCode::MissingReserve
+            );
+            for (info, reserve_key) in ctx.remaining_accounts.iter().zip(pool.reserves.iter()) {
+                require_keys_eq!(info.key(), *reserve_key, ErrorCode::InvalidReserve);
+                require_keys_eq!(*info.owner, crate::ID, ErrorCode::InvalidReserve);
+                let reserve = Reserve::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+                require!(
+                    liquidation_bonus_is_solvent(reserve.config.liquidation_threshold, pool),
+                    ErrorCode::InvalidPoolConfig
+                );
+            }
+            for category in &pool.emode_categories {
+                require!(
+                    liquidation_bonus_is_solvent(category.liquidation_threshold, pool),
+                    ErrorCode::InvalidPoolConfig
+                );
+            }
+        }
+
+        emit!(PoolConfigUpdated {
+            pool: pool.key(),
+            config,
+        });
+
+        msg!("Pool config updated");
+        Ok(())
+    }
+
+    /// Add or replace an e-mode category of correlated assets (authority only)
+    /// Obligations in the category get its risk parameters on collateral from the category
+    pub fn set_emode_category(ctx: Context<UpdatePool>, category: EModeCategory) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        validate_emode_category(&category, pool)?;
+
+        match pool.emode_categories.iter_mut().find(|existing| existing.id == category.id) {
+            Some(existing) => *existing = category,
+            None => {
+                require!(
+                    pool.emode_categories.len() < MAX_EMODE_CATEGORIES,
+                    ErrorCode::TooManyEModeCategories
+                );
+                pool.emode_categories.push(category);
+            }
+        }
+
+        emit!(EModeCategoryUpdated {
+            pool: pool.key(),
+            category,
+        });
+
+        msg!("E-mode category {} updated", category.id);
+        Ok(())
+    }
+
+    /// Update a reserve's risk parameters and interest rate model (authority only)
+    pub fn update_reserve_config(ctx: Context<UpdateReserve>, config: ReserveConfig) -> Result<()> {
+        validate_reserve_config(&config, &ctx.accounts.reserve.key(), &ctx.accounts.pool)?;
+
+        // Deposits and isolated debt were taken on under the current mode and borrow reserve,
+        // so isolation can only be switched on or off, or pointed at another borrow reserve,
+        // while the reserve has neither. The debt ceiling can change at any time
+        let reserve = &mut ctx.accounts.reserve;
+        let borrow_reserve = |config: &ReserveConfig| config.isolation.map(|isolation| isolation.borrow_reserve);
+        if borrow_reserve(&config) != borrow_reserve(&reserve.config) {
+            require!(
+                reserve.total_collateral == 0 && reserve.isolated_debt == 0,
+                ErrorCode::IsolationModeInUse
+            );
+        }
+
+        // Settle interest under the old rate model before switching
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        reserve.config = config;
+
+        emit!(ReserveConfigUpdated {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            config,
+        });
+
+        msg!("Reserve config updated");
+        Ok(())
+    }
+
+    /// Point a reserve at new primary/secondary oracles, e.g. to rotate a feed (authority only)
+    /// The cached price is invalidated so the next refresh must read the new oracles
+    pub fn update_reserve_oracle(ctx: Context<UpdateReserve>, oracle: OracleConfig) -> Result<()> {
+        validate_oracle_config(&oracle)?;
+
+        let reserve = &mut ctx.accounts.reserve;
+        let old_oracle = reserve.oracle;
+        reserve.oracle = oracle;
+        reserve.last_update_slot = 0;
+
+        emit!(ReserveOracleUpdated {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            old_oracle,
+            new_oracle: oracle,
+        });
+
+        msg!("Reserve oracle updated");
+        Ok(())
+    }
+
+    /// Move accrued reserve factor fees out of a reserve's vault (authority only)
+    pub fn withdraw_protocol_fees(ctx: Context<WithdrawProtocolFees>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let reserve = &mut ctx.accounts.reserve;
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        // Fees accrue as interest is owed; only fees already paid in as cash can leave
+        let cash = ctx.accounts.reserve_vault.amount
+            .saturating_sub(reserve.total_collateral)
+            .saturating_sub(reserve.insurance_fund);
+        require!(amount <= reserve.protocol_fees, ErrorCode::InsufficientProtocolFees);
+        require!(amount <= cash, ErrorCode::InsufficientLiquidity);
+
+        let reserve_key = reserve.key();
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            reserve_key.as_ref(),
+            &[ctx.bumps.reserve_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.reserve_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        reserve.protocol_fees -= amount;
+
+        emit!(ProtocolFeesWithdrawn {
+            pool: reserve.pool,
+            reserve: reserve_key,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            protocol_fees: reserve.protocol_fees,
+        });
+
+        msg!("Protocol fees withdrawn: {}", amount);
+        Ok(())
+    }
+
+    /// Set the guardian key that may flip pause flags alongside the authority (authority only)
+    pub fn set_guardian(ctx: Context<UpdatePool>, guardian: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let old_guardian = pool.guardian;
+        pool.guardian = guardian;
+
+        emit!(GuardianUpdated {
+            pool: pool.key(),
+            old_guardian,
+            new_guardian: guardian,
+        });
+
+        msg!("Guardian set: {}", guardian);
+        Ok(())
+    }
+
+    /// Set the constant-product swap program liquidate_and_swap routes seized collateral through (authority only)
+    pub fn set_swap_program(ctx: Context<UpdatePool>, swap_program: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let old_swap_program = pool.swap_program;
+        pool.swap_program = swap_program;
+
+        emit!(SwapProgramUpdated {
+            pool: pool.key(),
+            old_swap_program,
+            new_swap_program: swap_program,
+        });
+
+        msg!("Swap program set: {}", swap_program);
+        Ok(())
+    }
+
+    /// Pause or resume deposits, borrows, withdrawals and liquidations independently
+    /// Signed by the authority or the guardian; repayments are never paused
+    pub fn set_pause_flags(ctx: Context<SetPauseFlags>, pause_flags: PauseFlags) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.pause_flags = pause_flags;
+
+        emit!(PauseFlagsUpdated {
+            pool: pool.key(),
+            signer: ctx.accounts.signer.key(),
+            pause_flags,
+        });
+
+        msg!("Pause flags updated");
+        Ok(())
+    }
+
+    /// Propose a new pool authority; takes effect once they accept (authority only)
+    pub fn propose_authority(ctx: Context<UpdatePool>, new_authority: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_authority = new_authority;
+
+        emit!(AuthorityProposed {
+            pool: pool.key(),
+            authority: pool.authority,
+            pending_authority: new_authority,
+        });
+
+        msg!("Authority proposed: {}", new_authority);
+        Ok(())
+    }
+
+    /// Accept a pending authority handoff (signed by the proposed authority)
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let old_authority = pool.authority;
+        pool.authority = ctx.accounts.pending_authority.key();
+        pool.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferred {
+            pool: pool.key(),
+            old_authority,
+            new_authority: pool.authority,
+        });
+
+        msg!("Authority transferred to {}", pool.authority);
+        Ok(())
+    }
+
+    /// Initialize a user obligation
     pub fn initialize_user(ctx: Context<InitializeUser>) -> Result<()> {
+        init_obligation(
+            &mut ctx.accounts.user_account,
+            ctx.accounts.owner.key(),
+            ctx.accounts.pool.key(),
+            ObligationMode::Cross,
+        )
+    }
+
+    /// Create an isolated obligation backed only by one isolated reserve
+    /// It can only borrow the reserve's designated asset, up to the reserve's isolated debt ceiling
+    pub fn initialize_isolated_user(ctx: Context<InitializeIsolatedUser>) -> Result<()> {
+        init_obligation(
+            &mut ctx.accounts.user_account,
+            ctx.accounts.owner.key(),
+            ctx.accounts.pool.key(),
+            ObligationMode::Isolated {
+                collateral_reserve: ctx.accounts.collateral_reserve.key(),
+            },
+        )
+    }
+
+    /// Accrue interest and cache a fresh oracle price on a reserve (permissionless)
+    /// Must run in the same slot as any instruction that prices an obligation
+    pub fn refresh_reserve(ctx: Context<RefreshReserve>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let reserve = &mut ctx.accounts.reserve;
+        let clock = Clock::get()?;
+
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        // 1. Read the primary oracle
+        let stake_pool_info = ctx.accounts.stake_pool.as_ref().map(|stake_pool| stake_pool.to_account_info());
+        let price = read_price(
+            &reserve.oracle.primary,
+            &ctx.accounts.oracle.to_account_info(),
+            stake_pool_info.as_ref(),
+            &reserve.mint,
+            pool,
+            &clock,
+        )?;
+
+        // 2. Cross-check against the secondary oracle, if configured
+        if let Some(secondary) = reserve.oracle.secondary {
+            let secondary_info = ctx.accounts.secondary_oracle
+                .as_ref()
+                .ok_or(ErrorCode::MissingSecondaryOracle)?
+                .to_account_info();
+            let secondary_price = read_price(
+                &secondary,
+                &secondary_info,
+                stake_pool_info.as_ref(),
+                &reserve.mint,
+                pool,
+                &clock,
+            )?;
+            check_price_divergence(&price, &secondary_price, reserve.oracle.max_divergence_bps)?;
+        }
+
+        reserve.price = price.price;
+        reserve.price_conf = price.conf;
+        reserve.price_exponent = price.exponent;
+        reserve.last_update_slot = clock.slot;
+
+        emit!(ReserveRefreshed {
+            reserve: reserve.key(),
+            price: price.price,
+            price_conf: price.conf,
+            price_exponent: price.exponent,
+            borrow_index: reserve.borrow_index,
+            supply_index: reserve.supply_index,
+            slot: clock.slot,
+        });
+
+        msg!("Reserve Price: {} x 10^{}", price.price, price.exponent);
+        Ok(())
+    }
+
+    /// Move an obligation into an e-mode category (0 leaves e-mode)
+    /// Every borrow must be in the category, and the obligation must stay within its borrow limit
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
+    pub fn set_obligation_emode(ctx: Context<SetObligationEMode>, emode_category: u8) -> Result<()> {
+        let pool = &ctx.accounts.pool;
         let user_account = &mut ctx.accounts.user_account;
-        user_account.owner = ctx.accounts.owner.key();
-        user_account.collateral_amount = 0;
-        user_account.debt_amount = 0;
-        user_account.pool = ctx.accounts.pool.key();
+        let clock = Clock::get()?;
+        let reserves = load_reserves(&pool.key(), ctx.remaining_accounts, &clock)?;
+
+        // 1. Only cross-margin obligations whose borrows all belong to the category can enter it
+        if emode_category != 0 {
+            find_emode_category(pool, emode_category)?;
+            require!(user_account.mode == ObligationMode::Cross, ErrorCode::IsolationViolation);
+            for borrow in user_account.borrows.iter() {
+                let reserve = find_reserve(&reserves, &borrow.reserve)?;
+                require!(
+                    reserve.config.emode_category == emode_category,
+                    ErrorCode::EModeViolation
+                );
+            }
+        }
+        user_account.emode_category = emode_category;
 
-        msg!("User account initialized");
+        // 2. Leaving or switching categories can lower the borrow limit
+        if !user_account.borrows.is_empty() {
+            let values = calculate_obligation_values(pool, user_account, &reserves)?;
+            require!(values.debt_value <= values.borrow_limit, ErrorCode::MaxLtvExceeded);
+        }
+
+        emit!(ObligationEModeSet {
+            pool: pool.key(),
+            user_account: user_account.key(),
+            emode_category,
+        });
+
+        msg!("Obligation e-mode category set to {}", emode_category);
         Ok(())
     }
 
-    /// Deposit collateral (for testing)
-    pub fn deposit_collateral(ctx: Context<UpdatePosition>, amount: u64) -> Result<()> {
+    /// Recompute and store an obligation's values and health factor (permissionless)
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
+    pub fn refresh_obligation(ctx: Context<RefreshObligation>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
         let user_account = &mut ctx.accounts.user_account;
-        user_account.collateral_amount = user_account.collateral_amount
-            .checked_add(amount)
+        let clock = Clock::get()?;
+
+        let reserves = load_reserves(&pool.key(), ctx.remaining_accounts, &clock)?;
+        let health = calculate_obligation_health(pool, user_account, &reserves, clock.slot)?;
+
+        user_account.collateral_value = health.collateral_value;
+        user_account.debt_value = health.debt_value;
+        user_account.health_factor = health.health_factor;
+        user_account.last_refresh_slot = health.slot;
+
+        emit!(ObligationRefreshed {
+            pool: pool.key(),
+            user_account: user_account.key(),
+            health,
+        });
+
+        msg!("Health Factor: {}", health.health_factor);
+        Ok(())
+    }
+
+    /// Read-only health query for bots and UIs; the result is returned via return data
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
+    pub fn get_health(ctx: Context<GetHealth>) -> Result<ObligationHealth> {
+        let clock = Clock::get()?;
+        let reserves = load_reserves(&ctx.accounts.pool.key(), ctx.remaining_accounts, &clock)?;
+        calculate_obligation_health(&ctx.accounts.pool, &ctx.accounts.user_account, &reserves, clock.slot)
+    }
+
+    /// Deposit collateral tokens into a reserve's vault
+    pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.pause_flags.deposits, ErrorCode::DepositsPaused);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let reserve = &mut ctx.accounts.reserve;
+        check_collateral_allowed(&ctx.accounts.user_account, &reserve.key(), reserve)?;
+
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        // Only what arrives after any transfer fee is credited
+        let received = amount
+            .checked_sub(transfer_fee(&ctx.accounts.mint, amount)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(received > 0, ErrorCode::InvalidAmount);
+
+        // 1. Transfer collateral from user to vault
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_collateral.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.reserve_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // 2. Credit the obligation and the reserve
+        add_deposit(&mut ctx.accounts.user_account, reserve.key(), received)?;
+        reserve.total_collateral = reserve.total_collateral
+            .checked_add(received)
             .ok_or(ErrorCode::MathOverflow)?;
 
-        msg!("Deposited {} collateral", amount);
+        emit!(Deposited {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            user_account: ctx.accounts.user_account.key(),
+            owner: ctx.accounts.owner.key(),
+            amount: received,
+        });
+
+        msg!("Deposited {} collateral", received);
         Ok(())
     }
 
-    /// Borrow (for testing)
-    pub fn borrow(ctx: Context<UpdatePosition>, amount: u64) -> Result<()> {
+    /// Borrow tokens out of a reserve's vault, up to the obligation's borrow limit
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
+    pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let reserve = &mut ctx.accounts.reserve;
         let user_account = &mut ctx.accounts.user_account;
-        user_account.debt_amount = user_account.debt_amount
-            .checked_add(amount)
+
+        require!(!pool.pause_flags.borrows, ErrorCode::BorrowsPaused);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        require!(available >= amount, ErrorCode::InsufficientLiquidity);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        // Round new shares up so the borrower never owes less than they took
+        let new_shares = debt_shares_from_amount(amount, reserve.borrow_index, Rounding::Up)?;
+
+        // The reserve's total debt must stay under its ceiling
+        if reserve.config.debt_ceiling > 0 {
+            let total_debt = debt_amount_from_shares(
+                reserve.total_debt_shares
+                    .checked_add(new_shares)
+                    .ok_or(ErrorCode::MathOverflow)?,
+                reserve.borrow_index,
+            )?;
+            require!(total_debt <= reserve.config.debt_ceiling, ErrorCode::DebtCeilingExceeded);
+        }
+
+        // Isolated obligations borrow only their collateral's designated reserve, up to its ceiling
+        if let ObligationMode::Isolated { collateral_reserve } = user_account.mode {
+            let isolated_reserve = isolated_reserve_account(&mut ctx.accounts.isolated_reserve, &collateral_reserve)?;
+            let isolation = isolated_reserve.config.isolation.ok_or(ErrorCode::IsolationViolation)?;
+            require_keys_eq!(isolation.borrow_reserve, reserve.key(), ErrorCode::IsolationViolation);
+            add_isolated_debt(isolated_reserve, amount)?;
+        }
+
+        // An obligation in e-mode only borrows assets of its category
+        if user_account.emode_category != 0 {
+            require!(
+                reserve.config.emode_category == user_account.emode_category,
+                ErrorCode::EModeViolation
+            );
+        }
+
+        add_borrow(user_account, reserve.key(), new_shares)?;
+
+        // 1. Check the obligation's debt after borrowing against its borrow limit
+        let reserves = load_reserves(&pool.key(), ctx.remaining_accounts, &clock)?;
+        let values = calculate_obligation_values(pool, user_account, &reserves)?;
+
+        msg!("Debt Value after borrow: {}", values.debt_value);
+        msg!("Borrow Limit: {}", values.borrow_limit);
+        require!(values.debt_value <= values.borrow_limit, ErrorCode::MaxLtvExceeded);
+
+        // 2. Transfer tokens from vault to user
+        let reserve_key = reserve.key();
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            reserve_key.as_ref(),
+            &[ctx.bumps.reserve_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.user_debt.to_account_info(),
+                    authority: ctx.accounts.reserve_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // 3. Record the debt as scaled shares on the reserve
+        reserve.total_debt_shares = reserve.total_debt_shares
+            .checked_add(new_shares)
             .ok_or(ErrorCode::MathOverflow)?;
 
+        emit!(Borrowed {
+            pool: pool.key(),
+            reserve: reserve_key,
+            user_account: user_account.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+            debt_shares: new_shares,
+            borrow_index: reserve.borrow_index,
+            debt_value: values.debt_value.raw(),
+            borrow_limit: values.borrow_limit.raw(),
+        });
+
         msg!("Borrowed {} debt", amount);
         Ok(())
     }
 
-    /// Execute liquidation with Pyth Pull Oracle
+    /// Repay borrowed tokens back into a reserve's vault
+    pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
+        let reserve = &mut ctx.accounts.reserve;
+        let user_account = &mut ctx.accounts.user_account;
+
+        let debt_shares = borrow_shares(user_account, &reserve.key());
+        require!(debt_shares > 0, ErrorCode::NoDebt);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        // Never pull more than is owed
+        let debt_amount = debt_amount_from_shares(debt_shares, reserve.borrow_index)?;
+        let repay_amount = amount.min(debt_amount);
+
+        let shares_to_burn = repaid_debt_shares(debt_shares, debt_amount, repay_amount, reserve.borrow_index)?;
+
+        // 1. Transfer tokens from user to vault; the user covers any transfer fee
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_debt.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.reserve_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount_with_transfer_fee(&ctx.accounts.mint, repay_amount)?,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // 2. Reduce the debt
+        remove_borrow(user_account, &reserve.key(), shares_to_burn)?;
+        reserve.total_debt_shares = reserve.total_debt_shares
+            .checked_sub(shares_to_burn)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        if let ObligationMode::Isolated { collateral_reserve } = user_account.mode {
+            let isolated_reserve = isolated_reserve_account(&mut ctx.accounts.isolated_reserve, &collateral_reserve)?;
+            remove_isolated_debt(isolated_reserve, repay_amount);
+        }
+
+        emit!(Repaid {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            user_account: user_account.key(),
+            owner: ctx.accounts.owner.key(),
+            amount: repay_amount,
+            shares_burned: shares_to_burn,
+            borrow_index: reserve.borrow_index,
+        });
+
+        msg!("Repaid {} debt", repay_amount);
+        msg!("Remaining debt shares: {}", debt_shares - shares_to_burn);
+        Ok(())
+    }
+
+    /// Withdraw collateral tokens from a reserve, keeping the obligation within its borrow limit
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let reserve = &mut ctx.accounts.reserve;
+        let user_account = &mut ctx.accounts.user_account;
+
+        require!(!pool.pause_flags.withdrawals, ErrorCode::WithdrawalsPaused);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        remove_deposit(user_account, &reserve.key(), amount)?;
+
+        // 1. Check the remaining collateral still covers the debt at LTV, as a borrow must;
+        // withdrawing down to the liquidation threshold would leave no buffer before liquidation
+        if !user_account.borrows.is_empty() {
+            let reserves = load_reserves(&pool.key(), ctx.remaining_accounts, &clock)?;
+            let values = calculate_obligation_values(pool, user_account, &reserves)?;
+
+            msg!("Borrow Limit after withdrawal: {}", values.borrow_limit);
+            require!(values.debt_value <= values.borrow_limit, ErrorCode::MaxLtvExceeded);
+        }
+
+        // 2. Transfer collateral from vault to user
+        let reserve_key = reserve.key();
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            reserve_key.as_ref(),
+            &[ctx.bumps.reserve_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.user_collateral.to_account_info(),
+                    authority: ctx.accounts.reserve_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // 3. Debit the reserve
+        reserve.total_collateral = reserve.total_collateral
+            .checked_sub(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        emit!(Withdrawn {
+            pool: pool.key(),
+            reserve: reserve_key,
+            user_account: user_account.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+        });
+
+        msg!("Withdrew {} collateral", amount);
+        Ok(())
+    }
+
+    /// Execute liquidation with Pyth Pull Oracle prices cached by refresh_reserve
+    /// Repays debt in `repay_reserve` and seizes collateral from `withdraw_reserve`
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
     pub fn execute_liquidation(
         ctx: Context<ExecuteLiquidation>,
         amount_to_liquidate: u64,
     ) -> Result<()> {
         let pool = &ctx.accounts.pool;
-        let user_account = &mut ctx.accounts.user_account;
-        let price_update = &ctx.accounts.price_update;
         let clock = Clock::get()?;
 
-        // 1. Read collateral price from Pyth Pull Oracle
-        let collateral_feed_id = get_feed_id_from_hex(&pool.collateral_feed_id)
-            .map_err(|_| ErrorCode::InvalidFeedId)?;
+        require!(!pool.pause_flags.liquidations, ErrorCode::LiquidationsPaused);
 
-        let collateral_price = price_update.get_price_no_older_than(
+        // Bring accrued interest into the debt before checking health
+        let repay_reserve = &mut ctx.accounts.repay_reserve;
+        let available = available_liquidity(ctx.accounts.repay_vault.amount, repay_reserve);
+        accrue_interest(repay_reserve, available, clock.unix_timestamp)?;
+
+        // 1-4. Check the obligation is liquidatable and size the repayment and seizure
+        let plan = plan_liquidation(
+            pool,
+            &ctx.accounts.user_account,
+            &ctx.accounts.repay_reserve,
+            &ctx.accounts.withdraw_reserve,
+            ctx.remaining_accounts,
             &clock,
-            60, // Max age 60 seconds (Pull oracle usually < 1s)
-            &collateral_feed_id
+            amount_to_liquidate,
         )?;
 
-        // 2. Read debt price from Pyth Pull Oracle
-        let debt_feed_id = get_feed_id_from_hex(&pool.debt_feed_id)
-            .map_err(|_| ErrorCode::InvalidFeedId)?;
-
-        let debt_price = price_update.get_price_no_older_than(
-            &clock,
-            60,
-            &debt_feed_id
+        // 5. Pull the repayment from the liquidator into the repay reserve's vault
+        // The liquidator covers any transfer fee, so the vault receives the full repayment
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.repay_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.liquidator_repay.to_account_info(),
+                    mint: ctx.accounts.repay_mint.to_account_info(),
+                    to: ctx.accounts.repay_vault.to_account_info(),
+                    authority: ctx.accounts.liquidator.to_account_info(),
+                },
+            ),
+            amount_with_transfer_fee(&ctx.accounts.repay_mint, plan.repay)?,
+            ctx.accounts.repay_mint.decimals,
         )?;
 
-        msg!("Collateral Price: ${}.{}", collateral_price.price, collateral_price.exponent);
-        msg!("Debt Price: ${}.{}", debt_price.price, debt_price.exponent);
+        // 6. Send the seized collateral (bonus included) to the liquidator
+        let withdraw_reserve_key = ctx.accounts.withdraw_reserve.key();
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            withdraw_reserve_key.as_ref(),
+            &[ctx.bumps.withdraw_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
 
-        // 3. Calculate health factor
-        // Health Factor = (Collateral Value * Liquidation Threshold) / Debt Value
-        // Normalized collateral value
-        let collateral_value = calculate_value(
-            user_account.collateral_amount,
-            collateral_price.price,
-            collateral_price.exponent,
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.withdraw_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.withdraw_vault.to_account_info(),
+                    mint: ctx.accounts.withdraw_mint.to_account_info(),
+                    to: ctx.accounts.liquidator_withdraw.to_account_info(),
+                    authority: ctx.accounts.withdraw_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            plan.seize,
+            ctx.accounts.withdraw_mint.decimals,
         )?;
 
-        let debt_value = calculate_value(
-            user_account.debt_amount,
-            debt_price.price,
-            debt_price.exponent,
+        // 7-8. Update the borrower's obligation and both reserves
+        settle_liquidation(
+            ctx.accounts.pool.key(),
+            ctx.accounts.liquidator.key(),
+            &mut ctx.accounts.user_account,
+            &mut ctx.accounts.repay_reserve,
+            &mut ctx.accounts.withdraw_reserve,
+            &plan,
         )?;
 
-        msg!("Collateral Value: {}", collateral_value);
-        msg!("Debt Value: {}", debt_value);
-
-        // Health Factor = (collateral_value * threshold) / debt_value
-        let threshold_adjusted_collateral = collateral_value
-            .checked_mul(pool.liquidation_threshold as u128)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::MathOverflow)?;
+        msg!("Liquidation successful!");
+        msg!("Debt repaid: {}", plan.repay);
+        msg!("Collateral seized: {}", plan.seize);
 
-        require!(debt_value > 0, ErrorCode::NoDebt);
+        Ok(())
+    }
 
-        let health_factor = threshold_adjusted_collateral
-            .checked_mul(PRECISION)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(debt_value)
-            .ok_or(ErrorCode::MathOverflow)?;
+    /// Liquidate and swap the seized collateral back to the debt asset in the same instruction
+    /// The collateral goes through the pool's swap program and the repayment is paid out of the
+    /// proceeds; the liquidator keeps the rest, which must be at least `min_profit`
+    /// Remaining accounts: every reserve the obligation references, refreshed this slot
+    pub fn liquidate_and_swap(
+        ctx: Context<LiquidateAndSwap>,
+        amount_to_liquidate: u64,
+        min_profit: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let clock = Clock::get()?;
 
-        msg!("Health Factor: {}", health_factor);
+        require!(!pool.pause_flags.liquidations, ErrorCode::LiquidationsPaused);
+        require_keys_neq!(pool.swap_program, Pubkey::default(), ErrorCode::SwapProgramNotSet);
 
-        // 4. Check if liquidatable (health factor < 1.0 in our precision)
-        require!(health_factor < PRECISION, ErrorCode::PositionHealthy);
+        // Bring accrued interest into the debt before checking health
+        let repay_reserve = &mut ctx.accounts.repay_reserve;
+        let available = available_liquidity(ctx.accounts.repay_vault.amount, repay_reserve);
+        accrue_interest(repay_reserve, available, clock.unix_timestamp)?;
 
-        // 5. Calculate liquidation amounts
-        let max_liquidatable = user_account.debt_amount / 2; // Max 50% of debt
-        let actual_liquidation = amount_to_liquidate.min(max_liquidatable);
+        // 1. Check the obligation is liquidatable and size the repayment and seizure
+        let plan = plan_liquidation(
+            pool,
+            &ctx.accounts.user_account,
+            &ctx.accounts.repay_reserve,
+            &ctx.accounts.withdraw_reserve,
+            ctx.remaining_accounts,
+            &clock,
+            amount_to_liquidate,
+        )?;
 
-        require!(actual_liquidation > 0, ErrorCode::InvalidLiquidationAmount);
+        // 2. Send the seized collateral to the liquidator's collateral account
+        let collateral_before = ctx.accounts.liquidator_withdraw.amount;
+        let withdraw_reserve_key = ctx.accounts.withdraw_reserve.key();
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            withdraw_reserve_key.as_ref(),
+            &[ctx.bumps.withdraw_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
 
-        // Calculate collateral to seize (with bonus)
-        let debt_value_liquidated = calculate_value(
-            actual_liquidation,
-            debt_price.price,
-            debt_price.exponent,
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.withdraw_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.withdraw_vault.to_account_info(),
+                    mint: ctx.accounts.withdraw_mint.to_account_info(),
+                    to: ctx.accounts.liquidator_withdraw.to_account_info(),
+                    authority: ctx.accounts.withdraw_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            plan.seize,
+            ctx.accounts.withdraw_mint.decimals,
         )?;
+        ctx.accounts.liquidator_withdraw.reload()?;
+        let collateral_received = ctx.accounts.liquidator_withdraw.amount
+            .checked_sub(collateral_before)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-        let collateral_value_to_seize = debt_value_liquidated
-            .checked_mul(10000 + pool.liquidation_bonus_bps as u128)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(10000)
+        // 3. Swap all of it for the debt asset; the proceeds must cover the repayment,
+        // including any transfer fee on it, plus the liquidator's minimum profit
+        let repay_transfer = amount_with_transfer_fee(&ctx.accounts.repay_mint, plan.repay)?;
+        let minimum_proceeds = repay_transfer
+            .checked_add(min_profit)
             .ok_or(ErrorCode::MathOverflow)?;
+        let proceeds_before = ctx.accounts.liquidator_repay.amount;
 
-        // Convert back to collateral amount
-        let collateral_to_seize = calculate_amount_from_value(
-            collateral_value_to_seize,
-            collateral_price.price,
-            collateral_price.exponent,
+        invoke_swap(
+            &ctx.accounts.swap_program,
+            &[
+                ctx.accounts.swap_pool.to_account_info(),
+                ctx.accounts.swap_collateral_vault.to_account_info(),
+                ctx.accounts.swap_debt_vault.to_account_info(),
+                ctx.accounts.withdraw_mint.to_account_info(),
+                ctx.accounts.repay_mint.to_account_info(),
+                ctx.accounts.liquidator_withdraw.to_account_info(),
+                ctx.accounts.liquidator_repay.to_account_info(),
+                ctx.accounts.liquidator.to_account_info(),
+                ctx.accounts.withdraw_token_program.to_account_info(),
+                ctx.accounts.repay_token_program.to_account_info(),
+            ],
+            collateral_received,
+            minimum_proceeds,
         )?;
 
+        // 4. The liquidator must not be left holding collateral, and must clear the profit floor
+        ctx.accounts.liquidator_withdraw.reload()?;
+        ctx.accounts.liquidator_repay.reload()?;
         require!(
-            collateral_to_seize <= user_account.collateral_amount,
-            ErrorCode::InsufficientCollateral
+            ctx.accounts.liquidator_withdraw.amount == collateral_before,
+            ErrorCode::CollateralNotSwapped
         );
+        let proceeds = ctx.accounts.liquidator_repay.amount.saturating_sub(proceeds_before);
+        require!(proceeds >= minimum_proceeds, ErrorCode::InsufficientSwapProceeds);
+
+        // 5. Repay the debt out of the proceeds
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.repay_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.liquidator_repay.to_account_info(),
+                    mint: ctx.accounts.repay_mint.to_account_info(),
+                    to: ctx.accounts.repay_vault.to_account_info(),
+                    authority: ctx.accounts.liquidator.to_account_info(),
+                },
+            ),
+            repay_transfer,
+            ctx.accounts.repay_mint.decimals,
+        )?;
+
+        // 6. Update the borrower's obligation and both reserves
+        settle_liquidation(
+            ctx.accounts.pool.key(),
+            ctx.accounts.liquidator.key(),
+            &mut ctx.accounts.user_account,
+            &mut ctx.accounts.repay_reserve,
+            &mut ctx.accounts.withdraw_reserve,
+            &plan,
+        )?;
+
+        let profit = proceeds - repay_transfer;
+        emit!(CollateralSwapped {
+            pool: ctx.accounts.pool.key(),
+            user_account: ctx.accounts.user_account.key(),
+            liquidator: ctx.accounts.liquidator.key(),
+            swap_program: ctx.accounts.swap_program.key(),
+            collateral_in: collateral_received,
+            proceeds,
+            profit,
+        });
 
-        // 6. Execute liquidation
-        user_account.debt_amount = user_account.debt_amount
-            .checked_sub(actual_liquidation)
+        msg!("Liquidation with swap successful!");
+        msg!("Debt repaid: {}", plan.repay);
+        msg!("Collateral swapped: {} for {}", collateral_received, proceeds);
+        msg!("Profit: {}", profit);
+
+        Ok(())
+    }
+
+    /// Supply liquidity to a reserve in exchange for receipt tokens
+    /// Each receipt is worth supply_index / INDEX_PRECISION tokens, which grows as borrowers pay interest
+    pub fn supply(ctx: Context<SupplyLiquidity>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.pause_flags.deposits, ErrorCode::DepositsPaused);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let reserve = &mut ctx.accounts.reserve;
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        // Receipts are minted for what arrives after any transfer fee, rounded down
+        // so a supplier never claims more than they brought
+        let received = amount
+            .checked_sub(transfer_fee(&ctx.accounts.mint, amount)?)
             .ok_or(ErrorCode::MathOverflow)?;
+        let receipts = supply_shares_from_amount(received, reserve.supply_index)?;
+        require!(receipts > 0, ErrorCode::InvalidAmount);
+
+        // 1. Transfer liquidity from supplier to vault
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.reserve_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // 2. Mint receipt tokens to the supplier
+        let reserve_key = reserve.key();
+        let mint_seeds = &[
+            b"receipt_mint".as_ref(),
+            reserve_key.as_ref(),
+            &[ctx.bumps.receipt_mint],
+        ];
+        let mint_signer = &[&mint_seeds[..]];
+
+        anchor_spl::token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    to: ctx.accounts.user_receipt.to_account_info(),
+                    authority: ctx.accounts.receipt_mint.to_account_info(),
+                },
+                mint_signer,
+            ),
+            receipts,
+        )?;
 
-        user_account.collateral_amount = user_account.collateral_amount
-            .checked_sub(collateral_to_seize)
+        reserve.total_supply_shares = reserve.total_supply_shares
+            .checked_add(receipts)
             .ok_or(ErrorCode::MathOverflow)?;
 
-        msg!("Liquidation successful!");
-        msg!("Debt repaid: {}", actual_liquidation);
-        msg!("Collateral seized: {}", collateral_to_seize);
+        emit!(Supplied {
+            pool: reserve.pool,
+            reserve: reserve_key,
+            owner: ctx.accounts.owner.key(),
+            amount: received,
+            receipts,
+            supply_index: reserve.supply_index,
+        });
 
+        msg!("Supplied {} for {} receipts", received, receipts);
         Ok(())
     }
-}
 
-// Helper function to calculate USD value with proper decimal handling
-fn calculate_value(amount: u64, price: i64, exponent: i32) -> Result<u128> {
-    let amount_scaled = (amount as u128)
-        .checked_mul(PRECISION)
-        .ok_or(ErrorCode::MathOverflow)?;
+    /// Burn receipt tokens for their share of a reserve's liquidity, interest included
+    pub fn redeem(ctx: Context<SupplyLiquidity>, receipts: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.pause_flags.withdrawals, ErrorCode::WithdrawalsPaused);
+        require!(receipts > 0, ErrorCode::InvalidAmount);
 
-    let price_abs = (price.abs() as u128);
+        let reserve = &mut ctx.accounts.reserve;
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
 
-    // Handle negative exponents (price is in format price * 10^exponent)
-    let value = if exponent < 0 {
-        let divisor = 10u128.pow(exponent.abs() as u32);
-        amount_scaled
-            .checked_mul(price_abs)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(divisor)
-            .ok_or(ErrorCode::MathOverflow)?
-    } else {
-        let multiplier = 10u128.pow(exponent as u32);
-        amount_scaled
-            .checked_mul(price_abs)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_mul(multiplier)
-            .ok_or(ErrorCode::MathOverflow)?
-    };
+        // Redemptions round down so the reserve never pays out more than receipts are worth
+        let amount = supply_amount_from_shares(receipts, reserve.supply_index)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(available >= amount, ErrorCode::InsufficientLiquidity);
 
-    Ok(value)
-}
+        // 1. Burn the supplier's receipts
+        anchor_spl::token_interface::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    from: ctx.accounts.user_receipt.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            receipts,
+        )?;
 
-// Helper function to convert USD value back to token amount
-fn calculate_amount_from_value(value: u128, price: i64, exponent: i32) -> Result<u64> {
-    let price_abs = price.abs() as u128;
+        // 2. Transfer the underlying from vault to supplier
+        let reserve_key = reserve.key();
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            reserve_key.as_ref(),
+            &[ctx.bumps.reserve_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
 
-    let amount = if exponent < 0 {
-        let multiplier = 10u128.pow(exponent.abs() as u32);
-        value
-            .checked_mul(multiplier)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(price_abs)
-            .ok_or(ErrorCode::MathOverflow)?
-    } else {
-        let divisor = 10u128.pow(exponent as u32);
-        value
-            .checked_div(price_abs)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(divisor)
-            .ok_or(ErrorCode::MathOverflow)?
-    };
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.user_token.to_account_info(),
+                    authority: ctx.accounts.reserve_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
 
-    let final_amount = amount
-        .checked_div(PRECISION)
-        .ok_or(ErrorCode::MathOverflow)?;
+        reserve.total_supply_shares = reserve.total_supply_shares
+            .checked_sub(receipts)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-    Ok(final_amount as u64)
-}
+        emit!(Redeemed {
+            pool: reserve.pool,
+            reserve: reserve_key,
+            owner: ctx.accounts.owner.key(),
+            amount,
+            receipts,
+            supply_index: reserve.supply_index,
+        });
 
-// Account Structures
+        msg!("Redeemed {} receipts for {}", receipts, amount);
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct InitializePool<'info> {
-    #[account(init, payer = authority, space = 8 + LendingPool::INIT_SPACE)]
-    pub pool: Account<'info, LendingPool>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    /// Add tokens to a reserve's insurance fund, which absorbs bad debt before suppliers do
+    pub fn fund_insurance(ctx: Context<FundInsurance>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let received = amount
+            .checked_sub(transfer_fee(&ctx.accounts.mint, amount)?)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-#[derive(Accounts)]
-pub struct InitializeUser<'info> {
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.funder_token.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.reserve_vault.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        let reserve = &mut ctx.accounts.reserve;
+        reserve.insurance_fund = reserve.insurance_fund
+            .checked_add(received)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        emit!(InsuranceFunded {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            funder: ctx.accounts.funder.key(),
+            amount: received,
+            insurance_fund: reserve.insurance_fund,
+        });
+
+        msg!("Insurance fund: {}", reserve.insurance_fund);
+        Ok(())
+    }
+
+    /// Write off an obligation's remaining debt in a reserve once it holds no collateral
+    /// (permissionless; covers borrows left behind in reserves other than the one liquidated)
+    pub fn write_off_bad_debt(ctx: Context<WriteOffBadDebt>) -> Result<()> {
+        let reserve = &mut ctx.accounts.reserve;
+        let user_account = &mut ctx.accounts.user_account;
+
+        require!(!ctx.accounts.pool.pause_flags.liquidations, ErrorCode::LiquidationsPaused);
+        require!(user_account.deposits.is_empty(), ErrorCode::ObligationHasCollateral);
+        require!(borrow_shares(user_account, &reserve.key()) > 0, ErrorCode::NoDebt);
+
+        let clock = Clock::get()?;
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        accrue_interest(reserve, available, clock.unix_timestamp)?;
+
+        let reserve_key = reserve.key();
+        let user_account_key = user_account.key();
+        let debt_written_off = socialize_bad_debt(
+            reserve,
+            reserve_key,
+            user_account,
+            ctx.accounts.pool.key(),
+            user_account_key,
+        )?;
+
+        if let ObligationMode::Isolated { collateral_reserve } = user_account.mode {
+            let isolated_reserve = isolated_reserve_account(&mut ctx.accounts.isolated_reserve, &collateral_reserve)?;
+            remove_isolated_debt(isolated_reserve, debt_written_off);
+        }
+        Ok(())
+    }
+
+    /// Lend reserve liquidity for the span of one transaction
+    /// A matching flash_repay for the same reserve and amount must follow later in the transaction
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let reserve = &mut ctx.accounts.reserve;
+
+        require!(!pool.pause_flags.borrows, ErrorCode::BorrowsPaused);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(reserve.flash_loan_amount == 0, ErrorCode::FlashLoanActive);
+
+        let available = available_liquidity(ctx.accounts.reserve_vault.amount, reserve);
+        require!(available >= amount, ErrorCode::InsufficientLiquidity);
+
+        // 1. Introspect the transaction: top-level call with exactly one matching repayment
+        let reserve_key = reserve.key();
+        check_flash_repay_follows(&ctx.accounts.instructions.to_account_info(), &reserve_key, amount)?;
+
+        // 2. Record the loan before handing out tokens
+        reserve.flash_loan_amount = amount;
+
+        let vault_seeds = &[
+            b"reserve_vault".as_ref(),
+            reserve_key.as_ref(),
+            &[ctx.bumps.reserve_vault],
+        ];
+        let vault_signer = &[&vault_seeds[..]];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.user_token.to_account_info(),
+                    authority: ctx.accounts.reserve_vault.to_account_info(),
+                },
+                vault_signer,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        emit!(FlashBorrowed {
+            pool: pool.key(),
+            reserve: reserve_key,
+            user: ctx.accounts.user.key(),
+            amount,
+        });
+
+        msg!("Flash borrowed {}", amount);
+        Ok(())
+    }
+
+    /// Return a flash loan plus the reserve's flash loan fee, which stays with suppliers
+    pub fn flash_repay(ctx: Context<FlashRepay>, amount: u64) -> Result<()> {
+        let reserve = &mut ctx.accounts.reserve;
+
+        require!(
+            reserve.flash_loan_amount > 0 && reserve.flash_loan_amount == amount,
+            ErrorCode::FlashLoanNotActive
+        );
+        check_top_level_call(&ctx.accounts.instructions.to_account_info())?;
+
+        // Fee rounds up so a loan is never free
+        let fee = mul_div(amount as u128, reserve.config.flash_loan_fee_bps as u128, 10000, Rounding::Up)
+            .and_then(atomliq_math::to_u64)
+            .map_err(math_error)?;
+        let repay_amount = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+
+        // The borrower covers any transfer fee, so the vault receives the loan and fee in full
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.reserve_vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_with_transfer_fee(&ctx.accounts.mint, repay_amount)?,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        reserve.flash_loan_amount = 0;
+        credit_suppliers(reserve, fee)?;
+
+        emit!(FlashRepaid {
+            pool: reserve.pool,
+            reserve: reserve.key(),
+            user: ctx.accounts.user.key(),
+            amount,
+            fee,
+        });
+
+        msg!("Flash repaid {} (fee {})", amount, fee);
+        Ok(())
+    }
+}
+
+// Helper function to ensure the current instruction was not invoked through CPI
+fn check_top_level_call(instructions: &AccountInfo) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)?;
+    let current_ix = load_instruction_at_checked(current_index as usize, instructions)?;
+    require_keys_eq!(current_ix.program_id, crate::ID, ErrorCode::FlashLoanCpi);
+    Ok(())
+}
+
+// Helper function to find the flash_repay that closes a flash_borrow
+// Scans the rest of the transaction; a second flash_borrow of the same reserve first is reentrancy
+fn check_flash_repay_follows(instructions: &AccountInfo, reserve: &Pubkey, amount: u64) -> Result<()> {
+    check_top_level_call(instructions)?;
+
+    let mut index = load_current_index_checked(instructions)? as usize + 1;
+    while let Ok(ix) = load_instruction_at_checked(index, instructions) {
+        index += 1;
+
+        // FlashBorrow and FlashRepay both list the reserve as their second account
+        if ix.program_id != crate::ID || ix.accounts.get(1).map(|meta| &meta.pubkey) != Some(reserve) {
+            continue;
+        }
+        if ix.data.starts_with(instruction::FlashBorrow::DISCRIMINATOR) {
+            return err!(ErrorCode::FlashLoanActive);
+        }
+        if ix.data.starts_with(instruction::FlashRepay::DISCRIMINATOR) {
+            let disc_len = instruction::FlashRepay::DISCRIMINATOR.len();
+            let repay_amount = ix.data
+                .get(disc_len..disc_len + 8)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u64::from_le_bytes)
+                .ok_or(ErrorCode::FlashRepayMissing)?;
+            require!(repay_amount == amount, ErrorCode::FlashRepayMissing);
+            return Ok(());
+        }
+    }
+
+    err!(ErrorCode::FlashRepayMissing)
+}
+
+// Helper function to check a reserve's risk parameters and rate model
+fn validate_reserve_config(config: &ReserveConfig, reserve: &Pubkey, pool: &LendingPool) -> Result<()> {
+    require!(
+        config.liquidation_threshold > 0 && config.liquidation_threshold <= 10000,
+        ErrorCode::InvalidReserveConfig
+    );
+    // A zero bonus leaves liquidators nothing to earn; one above the pool cap is never paid
+    require!(
+        config.liquidation_bonus_bps > 0
+            && config.liquidation_bonus_bps <= pool.max_liquidation_bonus_bps,
+        ErrorCode::InvalidReserveConfig
+    );
+    require!(
+        liquidation_bonus_is_solvent(config.liquidation_threshold, pool),
+        ErrorCode::InvalidReserveConfig
+    );
+    // Borrowing up to LTV must never be instantly liquidatable
+    require!(
+        config.ltv_bps > 0 && config.ltv_bps < config.liquidation_threshold,
+        ErrorCode::InvalidMaxLtv
+    );
+    require!(
+        config.interest_rate_model.optimal_utilization_bps > 0
+            && config.interest_rate_model.optimal_utilization_bps < 10000,
+        ErrorCode::InvalidInterestRateModel
+    );
+    require!(config.flash_loan_fee_bps <= 10000, ErrorCode::InvalidReserveConfig);
+    require!(config.reserve_factor_bps <= 10000, ErrorCode::InvalidReserveConfig);
+    if config.emode_category != 0 {
+        find_emode_category(pool, config.emode_category)?;
+    }
+    if let Some(isolation) = config.isolation {
+        require!(
+            isolation.max_isolated_debt > 0 && isolation.borrow_reserve != Pubkey::default(),
+            ErrorCode::InvalidReserveConfig
+        );
+        // An isolated obligation cannot borrow the asset it posts as collateral
+        require_keys_neq!(isolation.borrow_reserve, *reserve, ErrorCode::InvalidReserveConfig);
+    }
+    Ok(())
+}
+
+// Helper function to check that seizing threshold × (1 + bonus) of the collateral stays below 100%
+// at the pool's bonus cap, the largest bonus a liquidation can pay whatever its base bonus. Then
+// a liquidation just below HF 1.0 raises the obligation's health instead of lowering it
+fn liquidation_bonus_is_solvent(liquidation_threshold: u16, pool: &LendingPool) -> bool {
+    (liquidation_threshold as u32) * (10000 + pool.max_liquidation_bonus_bps as u32) < 10000 * 10000
+}
+
+// Helper function to reject an unset feed id (all zero bytes)
+fn validate_feed_id(feed_id: &[u8; 32]) -> Result<()> {
+    require!(feed_id.iter().any(|byte| *byte != 0), ErrorCode::InvalidFeedId);
+    Ok(())
+}
+
+// Helper function to check pool-wide oracle and liquidation parameters
+fn validate_pool_config(config: &PoolConfig) -> Result<()> {
+    require!(config.max_price_age_secs > 0, ErrorCode::InvalidPoolConfig);
+    require!(
+        config.max_confidence_bps > 0 && config.max_confidence_bps < 10000,
+        ErrorCode::InvalidPoolConfig
+    );
+    require!(
+        config.close_factor_bps > 0 && config.close_factor_bps <= 10000,
+        ErrorCode::InvalidPoolConfig
+    );
+    require!(config.full_liquidation_hf_bps <= 10000, ErrorCode::InvalidPoolConfig);
+    require!(
+        config.max_liquidation_bonus_bps > 0 && config.max_liquidation_bonus_bps < 10000,
+        ErrorCode::InvalidPoolConfig
+    );
+    Ok(())
+}
+
+// Helper function to validate an e-mode category against the same bounds as a reserve config
+fn validate_emode_category(category: &EModeCategory, pool: &LendingPool) -> Result<()> {
+    require!(category.id != 0, ErrorCode::InvalidEModeCategory);
+    require!(
+        category.liquidation_threshold > 0 && category.liquidation_threshold <= 10000,
+        ErrorCode::InvalidEModeCategory
+    );
+    require!(
+        category.liquidation_bonus_bps > 0
+            && category.liquidation_bonus_bps <= pool.max_liquidation_bonus_bps,
+        ErrorCode::InvalidEModeCategory
+    );
+    require!(
+        liquidation_bonus_is_solvent(category.liquidation_threshold, pool),
+        ErrorCode::InvalidEModeCategory
+    );
+    require!(
+        category.ltv_bps > 0 && category.ltv_bps < category.liquidation_threshold,
+        ErrorCode::InvalidMaxLtv
+    );
+    Ok(())
+}
+
+// Helper function to reject mints whose extensions could freeze, move or block reserve tokens
+// Transfer fees and metadata are always accepted; freeze and permanent delegate powers only when
+// the authority allows them; anything else (e.g. transfer hooks, non-transferable) never
+fn validate_mint_extensions(mint: &InterfaceAccount<Mint>, mint_risks: &MintRiskOverrides) -> Result<()> {
+    require!(
+        mint.freeze_authority.is_none() || mint_risks.allow_freeze_authority,
+        ErrorCode::MintFreezeAuthorityNotAllowed
+    );
+
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    for extension in mint_state.get_extension_types()? {
+        match extension {
+            ExtensionType::TransferFeeConfig
+            | ExtensionType::MetadataPointer
+            | ExtensionType::TokenMetadata
+            | ExtensionType::GroupPointer
+            | ExtensionType::TokenGroup
+            | ExtensionType::GroupMemberPointer
+            | ExtensionType::TokenGroupMember => {}
+            ExtensionType::DefaultAccountState | ExtensionType::Pausable => require!(
+                mint_risks.allow_freeze_authority,
+                ErrorCode::MintFreezeAuthorityNotAllowed
+            ),
+            ExtensionType::PermanentDelegate => require!(
+                mint_risks.allow_permanent_delegate,
+                ErrorCode::MintPermanentDelegateNotAllowed
+            ),
+            _ => return err!(ErrorCode::UnsupportedMintExtension),
+        }
+    }
+    Ok(())
+}
+
+// Helper function to get the Token-2022 transfer fee withheld from a transfer of `amount`
+// SPL Token mints and mints without a transfer fee extension charge nothing
+fn transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => fee_config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or_else(|| error!(ErrorCode::MathOverflow)),
+        Err(_) => Ok(0),
+    }
+}
+
+// Helper function to get how much to send so that exactly `amount` arrives after the transfer fee
+fn amount_with_transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let fee = match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => fee_config
+            .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(ErrorCode::MathOverflow)?,
+        Err(_) => 0,
+    };
+    amount.checked_add(fee).ok_or(ErrorCode::MathOverflow.into())
+}
+
+// Helper function to check that an oracle source can produce a price
+fn validate_oracle_source(source: &OracleSource) -> Result<()> {
+    match source {
+        OracleSource::Pyth { feed_id } => validate_feed_id(feed_id),
+        OracleSource::Switchboard { feed } => {
+            require_keys_neq!(*feed, Pubkey::default(), ErrorCode::InvalidOracleConfig);
+            Ok(())
+        }
+        OracleSource::StakePool { feed_id, stake_pool } => {
+            require_keys_neq!(*stake_pool, Pubkey::default(), ErrorCode::InvalidOracleConfig);
+            validate_feed_id(feed_id)
+        }
+        OracleSource::Fixed { price, exponent } => {
+            require!(*price > 0, ErrorCode::NonPositivePrice);
+            require!(
+                (MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT).contains(exponent),
+                ErrorCode::InvalidPriceExponent
+            );
+            Ok(())
+        }
+    }
+}
+
+// Helper function to check a reserve's primary and optional secondary oracle
+fn validate_oracle_config(oracle: &OracleConfig) -> Result<()> {
+    validate_oracle_source(&oracle.primary)?;
+    if let Some(secondary) = &oracle.secondary {
+        validate_oracle_source(secondary)?;
+        require!(*secondary != oracle.primary, ErrorCode::InvalidOracleConfig);
+        require!(
+            oracle.max_divergence_bps > 0 && oracle.max_divergence_bps <= 10000,
+            ErrorCode::InvalidOracleConfig
+        );
+    }
+    Ok(())
+}
+
+// Helper function to read a fresh, sane price from any supported oracle source
+// `mint` is the reserve's mint, which a stake pool source must issue
+fn read_price(
+    source: &OracleSource,
+    oracle_info: &AccountInfo,
+    stake_pool_info: Option<&AccountInfo>,
+    mint: &Pubkey,
+    pool: &LendingPool,
+    clock: &Clock,
+) -> Result<OraclePrice> {
+    let price = match source {
+        OracleSource::Pyth { feed_id } => read_pyth_price(oracle_info, feed_id, pool, clock)?,
+        OracleSource::Switchboard { feed } => {
+            read_switchboard_price(oracle_info, feed, pool, clock)?
+        }
+        OracleSource::StakePool { feed_id, stake_pool } => {
+            let stake_pool_info = stake_pool_info.ok_or(ErrorCode::MissingStakePool)?;
+            let rate = read_stake_pool_rate(stake_pool_info, stake_pool, mint, clock)?;
+            let base = read_pyth_price(oracle_info, feed_id, pool, clock)?;
+            apply_stake_pool_rate(&base, &rate)?
+        }
+        OracleSource::Fixed { price, exponent } => OraclePrice {
+            price: *price,
+            conf: 0,
+            exponent: *exponent,
+        },
+    };
+
+    require!(price.price > 0, ErrorCode::NonPositivePrice);
+    require!(
+        (MIN_PRICE_EXPONENT..=MAX_PRICE_EXPONENT).contains(&price.exponent),
+        ErrorCode::InvalidPriceExponent
+    );
+
+    // Confidence must be a small fraction of the price: conf / price <= max_confidence
+    let max_conf = mul_div(
+        u128::try_from(price.price).map_err(|_| ErrorCode::NonPositivePrice)?,
+        pool.max_confidence_bps as u128,
+        10000,
+        Rounding::Down,
+    )
+    .map_err(math_error)?;
+    require!(
+        (price.conf as u128) <= max_conf,
+        ErrorCode::PriceConfidenceTooWide
+    );
+
+    Ok(price)
+}
+
+// Helper function to read a price for a feed id from a Pyth price update
+// Only fully Wormhole-verified updates posted by the Pyth receiver program are accepted
+fn read_pyth_price(
+    price_update_info: &AccountInfo,
+    feed_id: &[u8; 32],
+    pool: &LendingPool,
+    clock: &Clock,
+) -> Result<OraclePrice> {
+    require_keys_eq!(
+        *price_update_info.owner,
+        pyth_solana_receiver_sdk::ID,
+        ErrorCode::InvalidOracleOwner
+    );
+    let price_update = PriceUpdateV2::try_deserialize(&mut &price_update_info.try_borrow_data()?[..])
+        .map_err(|_| ErrorCode::InvalidPriceUpdate)?;
+    require!(
+        price_update.verification_level == VerificationLevel::Full,
+        ErrorCode::PriceNotFullyVerified
+    );
+
+    let price = price_update
+        .get_price_no_older_than(clock, pool.max_price_age_secs, feed_id)
+        .map_err(|err| match err {
+            GetPriceError::PriceTooOld => ErrorCode::StalePrice,
+            GetPriceError::MismatchedFeedId => ErrorCode::InvalidFeedId,
+            _ => ErrorCode::InvalidPriceUpdate,
+        })?;
+
+    Ok(OraclePrice {
+        price: price.price,
+        conf: price.conf,
+        exponent: price.exponent,
+    })
+}
+
+// Helper function to read the current result of a Switchboard On-Demand pull feed
+// Results are 18-decimal fixed point; the standard deviation serves as the confidence
+fn read_switchboard_price(
+    feed_info: &AccountInfo,
+    feed: &Pubkey,
+    pool: &LendingPool,
+    clock: &Clock,
+) -> Result<OraclePrice> {
+    require_keys_eq!(
+        *feed_info.owner,
+        SWITCHBOARD_ON_DEMAND_PROGRAM_ID,
+        ErrorCode::InvalidOracleOwner
+    );
+    require_keys_eq!(feed_info.key(), *feed, ErrorCode::InvalidFeedId);
+
+    let feed_data = PullFeedAccountData::parse(feed_info.try_borrow_data()?)
+        .map_err(|_| ErrorCode::InvalidPriceUpdate)?;
+    require!(feed_data.result.slot > 0, ErrorCode::InvalidPriceUpdate);
+
+    let age = clock.unix_timestamp
+        .checked_sub(feed_data.last_update_timestamp)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(
+        u64::try_from(age).is_ok_and(|age| age <= pool.max_price_age_secs),
+        ErrorCode::StalePrice
+    );
+
+    require!(feed_data.result.value > 0, ErrorCode::NonPositivePrice);
+    let mut value = feed_data.result.value;
+    let mut std_dev = feed_data.result.std_dev.max(0);
+    let mut exponent = -(switchboard_on_demand::PRECISION as i32);
+
+    // Drop decimals until the value fits the i64 price used everywhere else
+    while value > i64::MAX as i128 {
+        value /= 10;
+        std_dev /= 10;
+        exponent += 1;
+    }
+
+    Ok(OraclePrice {
+        price: i64::try_from(value).map_err(|_| ErrorCode::MathOverflow)?,
+        conf: u64::try_from(std_dev).map_err(|_| ErrorCode::MathOverflow)?,
+        exponent,
+    })
+}
+
+// Helper function to read an SPL stake pool's exchange rate of lamports per pool token
+// The pool must be owned by the stake pool program, issue the reserve's mint and be updated this epoch
+fn read_stake_pool_rate(
+    stake_pool_info: &AccountInfo,
+    stake_pool: &Pubkey,
+    mint: &Pubkey,
+    clock: &Clock,
+) -> Result<StakePoolRate> {
+    require_keys_eq!(*stake_pool_info.owner, SPL_STAKE_POOL_PROGRAM_ID, ErrorCode::InvalidStakePool);
+    require_keys_eq!(stake_pool_info.key(), *stake_pool, ErrorCode::InvalidStakePool);
+
+    let data = stake_pool_info.try_borrow_data()?;
+    require!(
+        data.len() >= STAKE_POOL_MIN_LEN && data[0] == STAKE_POOL_ACCOUNT_TYPE,
+        ErrorCode::InvalidStakePool
+    );
+    let read_u64 = |offset: usize| -> Result<u64> {
+        let bytes = data[offset..offset + 8].try_into().map_err(|_| ErrorCode::InvalidStakePool)?;
+        Ok(u64::from_le_bytes(bytes))
+    };
+    let pool_mint = Pubkey::try_from(&data[STAKE_POOL_MINT_OFFSET..STAKE_POOL_MINT_OFFSET + 32])
+        .map_err(|_| ErrorCode::InvalidStakePool)?;
+    require_keys_eq!(pool_mint, *mint, ErrorCode::InvalidStakePool);
+
+    // Balances are only brought up to date by the pool's per-epoch update
+    require!(
+        read_u64(STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET)? == clock.epoch,
+        ErrorCode::StaleStakePool
+    );
+
+    let rate = StakePoolRate {
+        total_lamports: read_u64(STAKE_POOL_TOTAL_LAMPORTS_OFFSET)?,
+        pool_token_supply: read_u64(STAKE_POOL_TOKEN_SUPPLY_OFFSET)?,
+    };
+    require!(rate.pool_token_supply > 0, ErrorCode::InvalidStakePool);
+    Ok(rate)
+}
+
+// Helper function to price a pool token as the base price times lamports per pool token
+// The price rounds down and the confidence up, so the LST is never overvalued by rounding
+fn apply_stake_pool_rate(base: &OraclePrice, rate: &StakePoolRate) -> Result<OraclePrice> {
+    let scale = |value: u128, rounding: Rounding| {
+        mul_div(value, rate.total_lamports as u128, rate.pool_token_supply as u128, rounding)
+            .map_err(math_error)
+    };
+    let price = scale(
+        u128::try_from(base.price).map_err(|_| ErrorCode::NonPositivePrice)?,
+        Rounding::Down,
+    )?;
+    let conf = scale(base.conf as u128, Rounding::Up)?;
+
+    Ok(OraclePrice {
+        price: i64::try_from(price).map_err(|_| ErrorCode::MathOverflow)?,
+        conf: u64::try_from(conf).map_err(|_| ErrorCode::MathOverflow)?,
+        exponent: base.exponent,
+    })
+}
+
+// Helper function to reject a primary price that strays too far from the secondary one
+// |primary - secondary| / primary <= max_divergence, compared at a common exponent
+fn check_price_divergence(
+    primary: &OraclePrice,
+    secondary: &OraclePrice,
+    max_divergence_bps: u16,
+) -> Result<()> {
+    let exponent = primary.exponent.min(secondary.exponent);
+    let scale = |price: &OraclePrice| -> Result<u128> {
+        let shift = u32::try_from(price.exponent - exponent).map_err(|_| ErrorCode::MathOverflow)?;
+        u128::try_from(price.price)
+            .map_err(|_| ErrorCode::NonPositivePrice)?
+            .checked_mul(10u128.checked_pow(shift).ok_or(ErrorCode::MathOverflow)?)
+            .ok_or(ErrorCode::MathOverflow.into())
+    };
+    let primary_scaled = scale(primary)?;
+    let secondary_scaled = scale(secondary)?;
+
+    let max_difference = mul_div(primary_scaled, max_divergence_bps as u128, 10000, Rounding::Down)
+        .map_err(math_error)?;
+    require!(
+        primary_scaled.abs_diff(secondary_scaled) <= max_difference,
+        ErrorCode::OracleDivergence
+    );
+    Ok(())
+}
+
+// Helper function to map shared math errors onto program errors
+fn math_error(err: MathError) -> Error {
+    match err {
+        MathError::Overflow | MathError::DivisionByZero => ErrorCode::MathOverflow.into(),
+        MathError::NonPositivePrice => ErrorCode::NonPositivePrice.into(),
+        MathError::InvalidExponent => ErrorCode::InvalidPriceExponent.into(),
+        MathError::InvalidDecimals => ErrorCode::UnsupportedMintDecimals.into(),
+    }
+}
+
+// Helper function to get a reserve's cached mid price
+fn reserve_price(reserve: &Reserve) -> Result<Price> {
+    Price::new(reserve.price, reserve.price_exponent).map_err(math_error)
+}
+
+// Helper function to value collateral at the bottom of the confidence interval (price - conf)
+fn collateral_price_lower_bound(reserve: &Reserve) -> Result<Price> {
+    let lower = reserve.price
+        .checked_sub(i64::try_from(reserve.price_conf).map_err(|_| ErrorCode::MathOverflow)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Price::new(lower, reserve.price_exponent).map_err(math_error)
+}
+
+// Helper function to value debt at the top of the confidence interval (price + conf)
+fn debt_price_upper_bound(reserve: &Reserve) -> Result<Price> {
+    let upper = reserve.price
+        .checked_add(i64::try_from(reserve.price_conf).map_err(|_| ErrorCode::MathOverflow)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Price::new(upper, reserve.price_exponent).map_err(math_error)
+}
+
+// Helper function to load the reserves passed as remaining accounts
+// Each must belong to the pool and have been refreshed in the current slot
+fn load_reserves(
+    pool: &Pubkey,
+    accounts: &[AccountInfo],
+    clock: &Clock,
+) -> Result<Vec<(Pubkey, Reserve)>> {
+    accounts
+        .iter()
+        .map(|info| {
+            require_keys_eq!(*info.owner, crate::ID, ErrorCode::InvalidReserve);
+            let reserve = Reserve::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            require_keys_eq!(reserve.pool, *pool, ErrorCode::InvalidReserve);
+            require!(reserve.last_update_slot == clock.slot, ErrorCode::ReserveStale);
+            Ok((info.key(), reserve))
+        })
+        .collect()
+}
+
+// Helper function to look up a loaded reserve by key
+fn find_reserve<'a>(reserves: &'a [(Pubkey, Reserve)], key: &Pubkey) -> Result<&'a Reserve> {
+    reserves
+        .iter()
+        .find(|(reserve_key, _)| reserve_key == key)
+        .map(|(_, reserve)| reserve)
+        .ok_or(error!(ErrorCode::MissingReserve))
+}
+
+// Aggregated USD values of an obligation
+struct ObligationValues {
+    collateral_value: Fixed,  // Sum of deposit values
+    borrow_limit: Fixed,      // Sum of deposit value * LTV
+    liquidation_limit: Fixed, // Sum of deposit value * liquidation threshold
+    debt_value: Fixed,        // Sum of borrow values, accrued interest included
+}
+
+// Helper function to price every deposit and borrow of an obligation
+fn calculate_obligation_values(
+    pool: &LendingPool,
+    user_account: &UserAccount,
+    reserves: &[(Pubkey, Reserve)],
+) -> Result<ObligationValues> {
+    let mut values = ObligationValues {
+        collateral_value: Fixed::ZERO,
+        borrow_limit: Fixed::ZERO,
+        liquidation_limit: Fixed::ZERO,
+        debt_value: Fixed::ZERO,
+    };
+
+    for deposit in user_account.deposits.iter() {
+        let reserve = find_reserve(reserves, &deposit.reserve)?;
+        let value = atomliq_math::token_value(
+            deposit.amount,
+            reserve.decimals,
+            collateral_price_lower_bound(reserve)?,
+            Rounding::Down,
+        )
+        .map_err(math_error)?;
+
+        values.collateral_value = values.collateral_value
+            .checked_add(value)
+            .map_err(math_error)?;
+        if !counts_as_collateral(user_account, &deposit.reserve, reserve) {
+            continue;
+        }
+        let params = risk_params(pool, user_account, reserve);
+        values.borrow_limit = values.borrow_limit
+            .checked_add(value.mul_bps(params.ltv_bps).map_err(math_error)?)
+            .map_err(math_error)?;
+        values.liquidation_limit = values.liquidation_limit
+            .checked_add(value.mul_bps(params.liquidation_threshold).map_err(math_error)?)
+            .map_err(math_error)?;
+    }
+
+    for borrow in user_account.borrows.iter() {
+        let reserve = find_reserve(reserves, &borrow.reserve)?;
+        let debt_amount = debt_amount_from_shares(borrow.debt_shares, reserve.borrow_index)?;
+        let value = atomliq_math::token_value(
+            debt_amount,
+            reserve.decimals,
+            debt_price_upper_bound(reserve)?,
+            Rounding::Up,
+        )
+        .map_err(math_error)?;
+
+        values.debt_value = values.debt_value
+            .checked_add(value)
+            .map_err(math_error)?;
+    }
+
+    Ok(values)
+}
+
+// Helper function to pick the risk parameters a deposit is valued with
+// Collateral in the obligation's e-mode category uses the category's; anything else the reserve's
+fn risk_params(pool: &LendingPool, user_account: &UserAccount, reserve: &Reserve) -> RiskParams {
+    let category = match user_account.emode_category {
+        0 => None,
+        id if id == reserve.config.emode_category => {
+            pool.emode_categories.iter().find(|category| category.id == id)
+        }
+        _ => None,
+    };
+    match category {
+        Some(category) => RiskParams {
+            ltv_bps: category.ltv_bps,
+            liquidation_threshold: category.liquidation_threshold,
+            liquidation_bonus_bps: category.liquidation_bonus_bps,
+        },
+        None => RiskParams {
+            ltv_bps: reserve.config.ltv_bps,
+            liquidation_threshold: reserve.config.liquidation_threshold,
+            liquidation_bonus_bps: reserve.config.liquidation_bonus_bps,
+        },
+    }
+}
+
+// Helper function to look up a pool's e-mode category by id
+fn find_emode_category(pool: &LendingPool, id: u8) -> Result<&EModeCategory> {
+    pool.emode_categories
+        .iter()
+        .find(|category| category.id == id)
+        .ok_or_else(|| error!(ErrorCode::UnknownEModeCategory))
+}
+
+// Helper function to summarize an obligation's values and health at a slot
+// An obligation without debt reports the maximum health factor
+fn calculate_obligation_health(
+    pool: &LendingPool,
+    user_account: &UserAccount,
+    reserves: &[(Pubkey, Reserve)],
+    slot: u64,
+) -> Result<ObligationHealth> {
+    let values = calculate_obligation_values(pool, user_account, reserves)?;
+    let health_factor = atomliq_math::health_factor(values.liquidation_limit, values.debt_value)
+        .map_err(math_error)?;
+
+    Ok(ObligationHealth {
+        collateral_value: values.collateral_value.raw(),
+        borrow_limit: values.borrow_limit.raw(),
+        liquidation_limit: values.liquidation_limit.raw(),
+        debt_value: values.debt_value.raw(),
+        health_factor: health_factor.raw(),
+        slot,
+    })
+}
+
+// Helper function to get the lendable balance of a reserve vault
+// Collateral, the insurance fund and protocol fees share the vault but are never lent out;
+// an in-flight flash loan still counts, since it returns within the transaction
+fn available_liquidity(vault_amount: u64, reserve: &Reserve) -> u64 {
+    vault_amount
+        .saturating_add(reserve.flash_loan_amount)
+        .saturating_sub(reserve.total_collateral)
+        .saturating_sub(reserve.insurance_fund)
+        .saturating_sub(reserve.protocol_fees)
+}
+
+// Helper function to share income among a reserve's suppliers by raising the supply index
+// Rounded down so receipts never claim more than the reserve holds
+fn credit_suppliers(reserve: &mut Reserve, amount: u64) -> Result<()> {
+    if amount == 0 || reserve.total_supply_shares == 0 {
+        return Ok(());
+    }
+    let index_growth = mul_div(
+        amount as u128,
+        INDEX_PRECISION,
+        reserve.total_supply_shares as u128,
+        Rounding::Down,
+    )
+    .map_err(math_error)?;
+    reserve.supply_index = reserve.supply_index
+        .checked_add(index_growth)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+// Repayment and seizure of a liquidation, sized before any tokens move
+struct LiquidationPlan {
+    health_factor: Fixed,       // Before liquidation
+    liquidation_bonus_bps: u16,
+    debt_shares: u64,           // Obligation's shares in the repay reserve
+    debt_amount: u64,           // Their value in tokens, accrued interest included
+    repay: u64,                 // Debt tokens repaid
+    seize: u64,                 // Collateral tokens seized, bonus included
+}
+
+// Helper function to check an obligation is liquidatable and size a liquidation of its borrow
+// in the repay reserve against its collateral in the withdraw reserve
+// The repay reserve must already have accrued interest
+fn plan_liquidation(
+    pool: &Account<LendingPool>,
+    user_account: &UserAccount,
+    repay_reserve: &Account<Reserve>,
+    withdraw_reserve: &Account<Reserve>,
+    remaining_accounts: &[AccountInfo],
+    clock: &Clock,
+    amount_to_liquidate: u64,
+) -> Result<LiquidationPlan> {
+    // 1. Both selected reserves must carry a price from this slot
+    require!(
+        repay_reserve.last_update_slot == clock.slot
+            && withdraw_reserve.last_update_slot == clock.slot,
+        ErrorCode::ReserveStale
+    );
+
+    msg!("Collateral Price: {} x 10^{}", withdraw_reserve.price, withdraw_reserve.price_exponent);
+    msg!("Debt Price: {} x 10^{}", repay_reserve.price, repay_reserve.price_exponent);
+
+    // 2. Calculate health factor across every deposit and borrow
+    let reserves = load_reserves(&pool.key(), remaining_accounts, clock)?;
+    let values = calculate_obligation_values(pool, user_account, &reserves)?;
+
+    msg!("Collateral Value: {}", values.collateral_value);
+    msg!("Debt Value: {}", values.debt_value);
+
+    require!(!values.debt_value.is_zero(), ErrorCode::NoDebt);
+
+    let health_factor = atomliq_math::health_factor(
+        values.liquidation_limit,
+        values.debt_value,
+    )
+    .map_err(math_error)?;
+
+    msg!("Health Factor: {}", health_factor);
+
+    // 3. Check if liquidatable (health factor < 1.0)
+    require!(health_factor < Fixed::ONE, ErrorCode::PositionHealthy);
+
+    // 4. Calculate liquidation amounts
+    let debt_shares = borrow_shares(user_account, &repay_reserve.key());
+    require!(debt_shares > 0, ErrorCode::NoDebt);
+    let debt_amount = debt_amount_from_shares(debt_shares, repay_reserve.borrow_index)?;
+
+    // Close factor caps the repayable share of the borrow, unless the obligation is
+    // dust-sized or deeply insolvent, in which case it can be closed in one go
+    let full_liquidation = values.debt_value <= Fixed::from_raw(pool.dust_debt_value as u128)
+        || health_factor < Fixed::from_bps(pool.full_liquidation_hf_bps);
+    let max_liquidatable = if full_liquidation {
+        debt_amount
+    } else {
+        atomliq_math::apply_bps(debt_amount as u128, pool.close_factor_bps)
+            .and_then(atomliq_math::to_u64)
+            .map_err(math_error)?
+    };
+    let requested_liquidation = amount_to_liquidate.min(max_liquidatable);
+
+    require!(requested_liquidation > 0, ErrorCode::InvalidLiquidationAmount);
+
+    let liquidation_bonus_bps = atomliq_math::liquidation_bonus_bps(
+        risk_params(pool, user_account, withdraw_reserve).liquidation_bonus_bps,
+        health_factor,
+        pool.max_liquidation_bonus_bps,
+    )
+    .map_err(math_error)?;
+    msg!("Liquidation Bonus: {} bps", liquidation_bonus_bps);
+
+    // Calculate collateral to seize (with bonus) at the mid price; the bonus is the
+    // liquidator's only incentive, not the width of the confidence interval.
+    // Seized collateral rounds down; if the collateral runs out, all of it is seized
+    // and the repayment shrinks to the debt it covers, rounded up
+    let collateral_deposited = deposit_amount(user_account, &withdraw_reserve.key());
+    require!(collateral_deposited > 0, ErrorCode::InsufficientCollateral);
+    let amounts = atomliq_math::liquidation_amounts(
+        requested_liquidation,
+        repay_reserve.decimals,
+        reserve_price(repay_reserve)?,
+        collateral_deposited,
+        withdraw_reserve.decimals,
+        reserve_price(withdraw_reserve)?,
+        liquidation_bonus_bps,
+    )
+    .map_err(math_error)?;
+    require!(amounts.seize > 0, ErrorCode::InvalidLiquidationAmount);
+
+    if amounts.collateral_exhausted {
+        msg!("Collateral exhausted, repay reduced to {}", amounts.repay);
+    }
+
+    Ok(LiquidationPlan {
+        health_factor,
+        liquidation_bonus_bps,
+        debt_shares,
+        debt_amount,
+        repay: amounts.repay,
+        seize: amounts.seize,
+    })
+}
+
+// Helper function to take a liquidation's repayment and seizure off the obligation and reserves
+// With no collateral left, the rest of the borrow is written off as bad debt
+fn settle_liquidation(
+    pool: Pubkey,
+    liquidator: Pubkey,
+    user_account: &mut Account<UserAccount>,
+    repay_reserve: &mut Account<Reserve>,
+    withdraw_reserve: &mut Account<Reserve>,
+    plan: &LiquidationPlan,
+) -> Result<()> {
+    let user_account_key = user_account.key();
+    let repay_reserve_key = repay_reserve.key();
+    let withdraw_reserve_key = withdraw_reserve.key();
+
+    // 1. Burn the repaid debt shares and the seized collateral
+    let shares_to_burn = repaid_debt_shares(
+        plan.debt_shares,
+        plan.debt_amount,
+        plan.repay,
+        repay_reserve.borrow_index,
+    )?;
+    remove_borrow(user_account, &repay_reserve_key, shares_to_burn)?;
+    repay_reserve.total_debt_shares = repay_reserve.total_debt_shares
+        .checked_sub(shares_to_burn)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    remove_deposit(user_account, &withdraw_reserve_key, plan.seize)?;
+    withdraw_reserve.total_collateral = withdraw_reserve.total_collateral
+        .checked_sub(plan.seize)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    // 2. With no collateral left, the rest of this borrow is bad debt
+    let mut debt_written_off = 0;
+    if user_account.deposits.is_empty() && borrow_shares(user_account, &repay_reserve_key) > 0 {
+        debt_written_off = socialize_bad_debt(
+            repay_reserve,
+            repay_reserve_key,
+            user_account,
+            pool,
+            user_account_key,
+        )?;
+    }
+
+    // An isolated obligation's only collateral is the withdraw reserve, which tracks its debt
+    if let ObligationMode::Isolated { .. } = user_account.mode {
+        remove_isolated_debt(withdraw_reserve, plan.repay.saturating_add(debt_written_off));
+    }
+
+    emit!(Liquidated {
+        pool,
+        user_account: user_account_key,
+        liquidator,
+        repay_reserve: repay_reserve_key,
+        withdraw_reserve: withdraw_reserve_key,
+        repay_price: repay_reserve.price,
+        repay_price_exponent: repay_reserve.price_exponent,
+        withdraw_price: withdraw_reserve.price,
+        withdraw_price_exponent: withdraw_reserve.price_exponent,
+        health_factor: plan.health_factor.raw(),
+        liquidation_bonus_bps: plan.liquidation_bonus_bps,
+        debt_repaid: plan.repay,
+        collateral_seized: plan.seize,
+    });
+
+    Ok(())
+}
+
+// Helper function to call the pool's swap program: `swap(amount_in, minimum_amount_out)` over
+// (swap pool, input vault, output vault, input mint, output mint, user source,
+// user destination, user, input token program, output token program)
+fn invoke_swap<'info>(
+    swap_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<()> {
+    let mut data = SWAP_IX_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+    let instruction = Instruction {
+        program_id: swap_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|info| AccountMeta {
+                pubkey: info.key(),
+                is_signer: info.is_signer,
+                is_writable: info.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let mut account_infos = accounts.to_vec();
+    account_infos.push(swap_program.clone());
+    invoke(&instruction, &account_infos)?;
+    Ok(())
+}
+
+// Helper function to write off an obligation's debt in a reserve, returning the amount written off
+// The insurance fund absorbs the loss first; any remainder lowers the supply index
+fn socialize_bad_debt(
+    reserve: &mut Reserve,
+    reserve_key: Pubkey,
+    user_account: &mut UserAccount,
+    pool: Pubkey,
+    user_account_key: Pubkey,
+) -> Result<u64> {
+    let debt_shares = borrow_shares(user_account, &reserve_key);
+    let debt_written_off = debt_amount_from_shares(debt_shares, reserve.borrow_index)?;
+
+    remove_borrow(user_account, &reserve_key, debt_shares)?;
+    reserve.total_debt_shares = reserve.total_debt_shares
+        .checked_sub(debt_shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    // 1. Insurance tokens become lendable liquidity in place of the lost debt
+    let covered_by_insurance = debt_written_off.min(reserve.insurance_fund);
+    reserve.insurance_fund -= covered_by_insurance;
+
+    // 2. Spread the rest across suppliers: every receipt loses loss / receipts, rounded up
+    let socialized = debt_written_off - covered_by_insurance;
+    if socialized > 0 && reserve.total_supply_shares > 0 {
+        let loss_per_share = mul_div(
+            socialized as u128,
+            INDEX_PRECISION,
+            reserve.total_supply_shares as u128,
+            Rounding::Up,
+        )
+        .map_err(math_error)?;
+        reserve.supply_index = reserve.supply_index.saturating_sub(loss_per_share);
+    }
+
+    emit!(BadDebtWrittenOff {
+        pool,
+        reserve: reserve_key,
+        user_account: user_account_key,
+        debt_written_off,
+        covered_by_insurance,
+        socialized,
+        supply_index: reserve.supply_index,
+    });
+
+    msg!("Bad debt written off: {}", debt_written_off);
+    Ok(debt_written_off)
+}
+
+// Helper function to set up a new obligation in the given margin mode
+fn init_obligation(
+    user_account: &mut Account<UserAccount>,
+    owner: Pubkey,
+    pool: Pubkey,
+    mode: ObligationMode,
+) -> Result<()> {
+    user_account.owner = owner;
+    user_account.pool = pool;
+    user_account.mode = mode;
+    user_account.emode_category = 0;
+    user_account.deposits = Vec::new();
+    user_account.borrows = Vec::new();
+    user_account.collateral_value = 0;
+    user_account.debt_value = 0;
+    user_account.health_factor = u128::MAX;
+    user_account.last_refresh_slot = 0;
+
+    emit!(UserInitialized {
+        pool,
+        user_account: user_account.key(),
+        owner,
+        mode,
+    });
+
+    msg!("User account initialized");
+    Ok(())
+}
+
+// Helper function to tell whether a reserve's deposits back an obligation's borrows
+// Isolated reserves count only in an obligation isolated on them; cross obligations ignore them
+fn counts_as_collateral(user_account: &UserAccount, reserve_key: &Pubkey, reserve: &Reserve) -> bool {
+    match user_account.mode {
+        ObligationMode::Cross => reserve.config.isolation.is_none(),
+        ObligationMode::Isolated { collateral_reserve } => collateral_reserve == *reserve_key,
+    }
+}
+
+// Helper function to reject collateral an obligation's margin mode does not allow
+fn check_collateral_allowed(user_account: &UserAccount, reserve_key: &Pubkey, reserve: &Reserve) -> Result<()> {
+    if counts_as_collateral(user_account, reserve_key, reserve) {
+        return Ok(());
+    }
+    match user_account.mode {
+        ObligationMode::Cross => err!(ErrorCode::IsolatedCollateral),
+        ObligationMode::Isolated { .. } => err!(ErrorCode::IsolationViolation),
+    }
+}
+
+// Helper function to get the collateral reserve an isolated obligation's instructions must pass
+fn isolated_reserve_account<'a>(
+    account: &'a mut Option<Account<'_, Reserve>>,
+    collateral_reserve: &Pubkey,
+) -> Result<&'a mut Reserve> {
+    let account = account.as_mut().ok_or(ErrorCode::MissingIsolatedReserve)?;
+    require_keys_eq!(account.key(), *collateral_reserve, ErrorCode::MissingIsolatedReserve);
+    Ok(&mut **account)
+}
+
+// Helper function to record debt backed by an isolated reserve, enforcing its ceiling
+// Tracked in the designated borrow reserve's tokens, by principal borrowed
+fn add_isolated_debt(reserve: &mut Reserve, amount: u64) -> Result<()> {
+    let isolation = reserve.config.isolation.ok_or(ErrorCode::IsolationViolation)?;
+    let isolated_debt = reserve.isolated_debt
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(
+        isolated_debt <= isolation.max_isolated_debt,
+        ErrorCode::IsolatedDebtCeilingExceeded
+    );
+    reserve.isolated_debt = isolated_debt;
+    Ok(())
+}
+
+// Helper function to release isolated debt; repayments include interest, so this saturates
+fn remove_isolated_debt(reserve: &mut Reserve, amount: u64) {
+    reserve.isolated_debt = reserve.isolated_debt.saturating_sub(amount);
+}
+
+// Helper function to credit collateral to an obligation, opening a deposit slot if needed
+fn add_deposit(user_account: &mut UserAccount, reserve: Pubkey, amount: u64) -> Result<()> {
+    if let Some(deposit) = user_account.deposits.iter_mut().find(|d| d.reserve == reserve) {
+        deposit.amount = deposit.amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        return Ok(());
+    }
+
+    require!(
+        user_account.deposits.len() < MAX_OBLIGATION_RESERVES,
+        ErrorCode::ObligationFull
+    );
+    user_account.deposits.push(ObligationDeposit { reserve, amount });
+    Ok(())
+}
+
+// Helper function to debit collateral from an obligation, closing the slot when emptied
+fn remove_deposit(user_account: &mut UserAccount, reserve: &Pubkey, amount: u64) -> Result<()> {
+    let index = user_account.deposits
+        .iter()
+        .position(|d| d.reserve == *reserve)
+        .ok_or(ErrorCode::InsufficientCollateral)?;
+
+    let deposit = &mut user_account.deposits[index];
+    deposit.amount = deposit.amount
+        .checked_sub(amount)
+        .ok_or(ErrorCode::InsufficientCollateral)?;
+
+    if deposit.amount == 0 {
+        user_account.deposits.remove(index);
+    }
+    Ok(())
+}
+
+// Helper function to get an obligation's collateral in a reserve
+fn deposit_amount(user_account: &UserAccount, reserve: &Pubkey) -> u64 {
+    user_account.deposits
+        .iter()
+        .find(|d| d.reserve == *reserve)
+        .map_or(0, |d| d.amount)
+}
+
+// Helper function to add debt shares to an obligation, opening a borrow slot if needed
+fn add_borrow(user_account: &mut UserAccount, reserve: Pubkey, debt_shares: u64) -> Result<()> {
+    if let Some(borrow) = user_account.borrows.iter_mut().find(|b| b.reserve == reserve) {
+        borrow.debt_shares = borrow.debt_shares
+            .checked_add(debt_shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        return Ok(());
+    }
+
+    require!(
+        user_account.borrows.len() < MAX_OBLIGATION_RESERVES,
+        ErrorCode::ObligationFull
+    );
+    user_account.borrows.push(ObligationBorrow { reserve, debt_shares });
+    Ok(())
+}
+
+// Helper function to burn debt shares from an obligation, closing the slot when repaid
+fn remove_borrow(user_account: &mut UserAccount, reserve: &Pubkey, debt_shares: u64) -> Result<()> {
+    let index = user_account.borrows
+        .iter()
+        .position(|b| b.reserve == *reserve)
+        .ok_or(ErrorCode::NoDebt)?;
+
+    let borrow = &mut user_account.borrows[index];
+    borrow.debt_shares = borrow.debt_shares
+        .checked_sub(debt_shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    if borrow.debt_shares == 0 {
+        user_account.borrows.remove(index);
+    }
+    Ok(())
+}
+
+// Helper function to get an obligation's debt shares in a reserve
+fn borrow_shares(user_account: &UserAccount, reserve: &Pubkey) -> u64 {
+    user_account.borrows
+        .iter()
+        .find(|b| b.reserve == *reserve)
+        .map_or(0, |b| b.debt_shares)
+}
+
+// Helper function to compute the annualized borrow rate (bps) from utilization (bps)
+// Rate rises gently along slope1 up to the optimal utilization, then steeply along slope2
+fn calculate_borrow_rate(model: &InterestRateModel, utilization_bps: u128) -> Result<u128> {
+    let optimal = model.optimal_utilization_bps as u128;
+
+    let rate = if utilization_bps <= optimal {
+        let variable = (model.slope1_bps as u128)
+            .checked_mul(utilization_bps)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(optimal)
+            .ok_or(ErrorCode::MathOverflow)?;
+        (model.base_rate_bps as u128)
+            .checked_add(variable)
+            .ok_or(ErrorCode::MathOverflow)?
+    } else {
+        let excess = (model.slope2_bps as u128)
+            .checked_mul(utilization_bps - optimal)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(10000 - optimal)
+            .ok_or(ErrorCode::MathOverflow)?;
+        (model.base_rate_bps as u128)
+            .checked_add(model.slope1_bps as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_add(excess)
+            .ok_or(ErrorCode::MathOverflow)?
+    };
+
+    Ok(rate)
+}
+
+// Helper function to grow the cumulative borrow index by the interest accrued since the last update
+fn accrue_interest(reserve: &mut Reserve, available_liquidity: u64, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(reserve.last_accrual_ts);
+    if elapsed <= 0 {
+        return Ok(());
+    }
+
+    let total_debt = debt_amount_from_shares(reserve.total_debt_shares, reserve.borrow_index)?;
+    let total_liquidity = (total_debt as u128)
+        .checked_add(available_liquidity as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    if total_debt > 0 && total_liquidity > 0 {
+        // Utilization = borrowed / (borrowed + available)
+        let utilization_bps = (total_debt as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(total_liquidity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let rate_bps = calculate_borrow_rate(&reserve.config.interest_rate_model, utilization_bps)?;
+
+        // index += index * rate * elapsed / year, rounded up so interest is never lost
+        let index_growth = mul_div(
+            reserve.borrow_index,
+            rate_bps
+                .checked_mul(elapsed as u128)
+                .ok_or(ErrorCode::MathOverflow)?,
+            10000 * SECONDS_PER_YEAR as u128,
+            Rounding::Up,
+        )
+        .map_err(math_error)?;
+
+        reserve.borrow_index = reserve.borrow_index
+            .checked_add(index_growth)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // The reserve factor's share of the new interest goes to the protocol, the rest to suppliers
+        let interest = debt_amount_from_shares(reserve.total_debt_shares, reserve.borrow_index)?
+            .saturating_sub(total_debt);
+        let protocol_fee = mul_div(
+            interest as u128,
+            reserve.config.reserve_factor_bps as u128,
+            10000,
+            Rounding::Down,
+        )
+        .and_then(atomliq_math::to_u64)
+        .map_err(math_error)?;
+        reserve.protocol_fees = reserve.protocol_fees
+            .checked_add(protocol_fee)
+            .ok_or(ErrorCode::MathOverflow)?;
+        credit_suppliers(reserve, interest - protocol_fee)?;
+    }
+
+    reserve.last_accrual_ts = now;
+    Ok(())
+}
+
+// Helper function to convert scaled debt shares into debt tokens (rounded up)
+fn debt_amount_from_shares(shares: u64, borrow_index: u128) -> Result<u64> {
+    mul_div(shares as u128, borrow_index, INDEX_PRECISION, Rounding::Up)
+        .and_then(atomliq_math::to_u64)
+        .map_err(math_error)
+}
+
+// Helper function to convert debt tokens into scaled debt shares
+// Minted shares round up and burned shares round down, so debt never shrinks by rounding
+fn debt_shares_from_amount(amount: u64, borrow_index: u128, rounding: Rounding) -> Result<u64> {
+    mul_div(amount as u128, INDEX_PRECISION, borrow_index, rounding)
+        .and_then(atomliq_math::to_u64)
+        .map_err(math_error)
+}
+
+// Helper function to convert supplied tokens into receipt shares (rounded down)
+fn supply_shares_from_amount(amount: u64, supply_index: u128) -> Result<u64> {
+    mul_div(amount as u128, INDEX_PRECISION, supply_index, Rounding::Down)
+        .and_then(atomliq_math::to_u64)
+        .map_err(math_error)
+}
+
+// Helper function to convert receipt shares into redeemable tokens (rounded down)
+fn supply_amount_from_shares(shares: u64, supply_index: u128) -> Result<u64> {
+    mul_div(shares as u128, supply_index, INDEX_PRECISION, Rounding::Down)
+        .and_then(atomliq_math::to_u64)
+        .map_err(math_error)
+}
+
+// Helper function to get the debt shares a repayment burns
+// A full repayment clears every share; partial repayments burn shares rounded down
+fn repaid_debt_shares(debt_shares: u64, debt_amount: u64, repay_amount: u64, borrow_index: u128) -> Result<u64> {
+    if repay_amount >= debt_amount {
+        return Ok(debt_shares);
+    }
+    Ok(debt_shares_from_amount(repay_amount, borrow_index, Rounding::Down)?.min(debt_shares))
+}
+
+// Account Structures
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(init, payer = authority, space = 8 + LendingPool::INIT_SPACE)]
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, LendingPool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateReserve<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawProtocolFees<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = reserve.mint)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetPauseFlags<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == pool.authority
+            || signer.key() == pool.guardian @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, LendingPool>,
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        constraint = pool.pending_authority == pending_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, LendingPool>,
+    pub pending_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddReserve<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, LendingPool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Reserve::INIT_SPACE,
+        seeds = [b"reserve", pool.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub reserve: Account<'info, Reserve>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = reserve_vault,
+        token::token_program = token_program,
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"receipt_mint", reserve.key().as_ref()],
+        bump,
+        mint::decimals = mint.decimals,
+        mint::authority = receipt_mint,
+        mint::token_program = token_program,
+    )]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeUser<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + UserAccount::INIT_SPACE,
+        seeds = [b"user", owner.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeIsolatedUser<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + UserAccount::INIT_SPACE,
+        seeds = [
+            b"user",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            collateral_reserve.key().as_ref(),
+        ],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    pub pool: Account<'info, LendingPool>,
+    #[account(
+        has_one = pool,
+        constraint = collateral_reserve.config.isolation.is_some() @ ErrorCode::NotIsolatedReserve
+    )]
+    pub collateral_reserve: Account<'info, Reserve>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshObligation<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+}
+
+#[derive(Accounts)]
+pub struct SetObligationEMode<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = owner, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetHealth<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshReserve<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: Owner and layout are checked in read_price for the reserve's primary source
+    /// (a Pyth PriceUpdateV2 or Switchboard pull feed; ignored for a fixed price; for a stake
+    /// pool source, the PriceUpdateV2 of the base asset)
+    pub oracle: UncheckedAccount<'info>,
+    /// CHECK: Checked in read_price against the reserve's secondary source, when configured
+    pub secondary_oracle: Option<UncheckedAccount<'info>>,
+    /// CHECK: Owner, layout and pool mint are checked in read_stake_pool_rate, for stake pool sources
+    pub stake_pool: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositCollateral<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, has_one = owner, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+        token::authority = owner,
+    )]
+    pub user_collateral: InterfaceAccount<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Borrow<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, has_one = owner, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+    )]
+    pub user_debt: InterfaceAccount<'info, TokenAccount>,
+    /// The collateral reserve of an isolated obligation (None for cross-margin)
+    #[account(mut, has_one = pool)]
+    pub isolated_reserve: Option<Account<'info, Reserve>>,
+    pub owner: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Repay<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, has_one = owner, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+        token::authority = owner,
+    )]
+    pub user_debt: InterfaceAccount<'info, TokenAccount>,
+    /// The collateral reserve of an isolated obligation (None for cross-margin)
+    #[account(mut, has_one = pool)]
+    pub isolated_reserve: Option<Account<'info, Reserve>>,
+    pub owner: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, has_one = owner, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+    )]
+    pub user_collateral: InterfaceAccount<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteLiquidation<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(mut, has_one = pool)]
+    pub repay_reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", repay_reserve.key().as_ref()],
+        bump
+    )]
+    pub repay_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = repay_reserve.mint)]
+    pub repay_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        has_one = pool,
+        constraint = withdraw_reserve.key() != repay_reserve.key() @ ErrorCode::InvalidLiquidationReserves
+    )]
+    pub withdraw_reserve: Account<'info, Reserve>,
     #[account(
-        init,
-        payer = owner,
-        space = 8 + UserAccount::INIT_SPACE,
-        seeds = [b"user", owner.key().as_ref(), pool.key().as_ref()],
+        mut,
+        seeds = [b"reserve_vault", withdraw_reserve.key().as_ref()],
         bump
     )]
-    pub user_account: Account<'info, UserAccount>,
+    pub withdraw_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = withdraw_reserve.mint)]
+    pub withdraw_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = repay_reserve.mint,
+        token::authority = liquidator,
+    )]
+    pub liquidator_repay: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = withdraw_reserve.mint,
+    )]
+    pub liquidator_withdraw: InterfaceAccount<'info, TokenAccount>,
+    pub liquidator: Signer<'info>,
+    #[account(address = repay_reserve.token_program)]
+    pub repay_token_program: Interface<'info, TokenInterface>,
+    #[account(address = withdraw_reserve.token_program)]
+    pub withdraw_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateAndSwap<'info> {
     pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(mut, has_one = pool)]
+    pub repay_reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", repay_reserve.key().as_ref()],
+        bump
+    )]
+    pub repay_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = repay_reserve.mint)]
+    pub repay_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        has_one = pool,
+        constraint = withdraw_reserve.key() != repay_reserve.key() @ ErrorCode::InvalidLiquidationReserves
+    )]
+    pub withdraw_reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", withdraw_reserve.key().as_ref()],
+        bump
+    )]
+    pub withdraw_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = withdraw_reserve.mint)]
+    pub withdraw_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = repay_reserve.mint,
+        token::authority = liquidator,
+    )]
+    pub liquidator_repay: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = withdraw_reserve.mint,
+        token::authority = liquidator,
+    )]
+    pub liquidator_withdraw: InterfaceAccount<'info, TokenAccount>,
+    pub liquidator: Signer<'info>,
+    #[account(address = repay_reserve.token_program)]
+    pub repay_token_program: Interface<'info, TokenInterface>,
+    #[account(address = withdraw_reserve.token_program)]
+    pub withdraw_token_program: Interface<'info, TokenInterface>,
+    /// CHECK: The pool's configured swap program, address-checked
+    #[account(executable, address = pool.swap_program @ ErrorCode::InvalidSwapProgram)]
+    pub swap_program: UncheckedAccount<'info>,
+    /// CHECK: Swap pool state, validated by the swap program
     #[account(mut)]
-    pub owner: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub swap_pool: UncheckedAccount<'info>,
+    /// CHECK: Swap pool vault receiving the collateral, validated by the swap program
+    #[account(mut)]
+    pub swap_collateral_vault: UncheckedAccount<'info>,
+    /// CHECK: Swap pool vault paying out the debt asset, validated by the swap program
+    #[account(mut)]
+    pub swap_debt_vault: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct UpdatePosition<'info> {
-    #[account(mut, has_one = owner)]
-    pub user_account: Account<'info, UserAccount>,
+pub struct SupplyLiquidity<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool, has_one = receipt_mint)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"receipt_mint", reserve.key().as_ref()],
+        bump
+    )]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+        token::authority = owner,
+    )]
+    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = receipt_mint,
+        token::authority = owner,
+    )]
+    pub user_receipt: InterfaceAccount<'info, TokenAccount>,
     pub owner: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct ExecuteLiquidation<'info> {
+pub struct FundInsurance<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+        token::authority = funder,
+    )]
+    pub funder_token: InterfaceAccount<'info, TokenAccount>,
+    pub funder: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WriteOffBadDebt<'info> {
     pub pool: Account<'info, LendingPool>,
     #[account(mut, has_one = pool)]
     pub user_account: Account<'info, UserAccount>,
-    pub price_update: Account<'info, PriceUpdateV2>,
-    pub liquidator: Signer<'info>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    /// The collateral reserve of an isolated obligation (None for cross-margin)
+    #[account(mut, has_one = pool)]
+    pub isolated_reserve: Option<Account<'info, Reserve>>,
+}
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = reserve.mint)]
+    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, address-checked
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    pub pool: Account<'info, LendingPool>,
+    #[account(mut, has_one = pool)]
+    pub reserve: Account<'info, Reserve>,
+    #[account(
+        mut,
+        seeds = [b"reserve_vault", reserve.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = reserve.mint,
+        token::authority = user,
+    )]
+    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    #[account(address = reserve.token_program)]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, address-checked
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
 }
 
 #[account]
 #[derive(InitSpace)]
 pub struct LendingPool {
     pub authority: Pubkey,
-    #[max_len(64)]
-    pub collateral_feed_id: String,
-    #[max_len(64)]
-    pub debt_feed_id: String,
+    pub pending_authority: Pubkey,  // Proposed authority awaiting acceptance (default if none)
+    #[max_len(MAX_RESERVES)]
+    pub reserves: Vec<Pubkey>,      // Reserves listed in this pool
+    #[max_len(MAX_EMODE_CATEGORIES)]
+    pub emode_categories: Vec<EModeCategory>, // Risk parameters for correlated assets
+    pub max_price_age_secs: u64,    // Oldest accepted Pyth publish time, in seconds
+    pub max_confidence_bps: u16,    // Widest accepted Pyth conf as a fraction of price
+    pub close_factor_bps: u16,      // Max share of a borrow repaid per liquidation
+    pub full_liquidation_hf_bps: u16, // Health factor (bps of 1.0) below which a borrow closes in full
+    pub dust_debt_value: u64,       // Obligation debt value at or below which a borrow closes in full
+    pub max_liquidation_bonus_bps: u16, // Cap on the health-scaled liquidation bonus
+    pub guardian: Pubkey,           // Emergency key allowed to set pause flags
+    pub pause_flags: PauseFlags,
+    pub swap_program: Pubkey,       // Swap program for liquidate_and_swap (default if none)
+}
+
+/// Efficiency-mode parameters for correlated assets (e.g. SOL and its LSTs, or stables).
+/// An obligation in the category values collateral from it with these instead of the reserve's.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct EModeCategory {
+    pub id: u8,                     // Referenced by reserves and obligations (0 = no category)
+    pub ltv_bps: u16,
+    pub liquidation_threshold: u16,
+    pub liquidation_bonus_bps: u16,
+}
+
+// Risk parameters a deposit is valued and liquidated with
+struct RiskParams {
+    ltv_bps: u16,
+    liquidation_threshold: u16,
+    liquidation_bonus_bps: u16,
+}
+
+// Operations halted by the authority or guardian
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct PauseFlags {
+    pub deposits: bool,
+    pub borrows: bool,
+    pub withdrawals: bool,
+    pub liquidations: bool,
+}
+
+// Pool-wide parameters set by update_pool_config
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PoolConfig {
+    pub max_price_age_secs: u64,
+    pub max_confidence_bps: u16,
+    pub close_factor_bps: u16,
+    pub full_liquidation_hf_bps: u16,
+    pub dust_debt_value: u64,
+    pub max_liquidation_bonus_bps: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Reserve {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,               // Mint decimals, read from the mint at add_reserve
+    pub token_program: Pubkey,      // SPL Token or Token-2022, whichever owns the mint
+    pub vault: Pubkey,              // PDA token account holding collateral and lendable liquidity
+    pub receipt_mint: Pubkey,       // PDA mint of the suppliers' interest-bearing receipt tokens
+    pub oracle: OracleConfig,       // Price source(s) read by refresh_reserve
+    pub config: ReserveConfig,
+    pub borrow_index: u128,         // Cumulative borrow index (INDEX_PRECISION = 1.0)
+    pub total_debt_shares: u64,     // Sum of all obligations' scaled debt
+    pub total_collateral: u64,      // Deposited collateral held in the vault (never lent out)
+    pub total_supply_shares: u64,   // Receipt tokens outstanding, each worth supply_index
+    pub insurance_fund: u64,        // Vault tokens set aside to absorb bad debt (never lent out)
+    pub supply_index: u128,         // Tokens per receipt share: raised by interest, lowered by bad debt
+    pub protocol_fees: u64,         // Reserve factor fees owed to the pool authority (never lent out)
+    pub isolated_debt: u64,         // Debt isolated obligations have borrowed against this reserve
+    pub last_accrual_ts: i64,       // Last time interest was accrued into the index
+    pub price: i64,                 // Last refreshed oracle price
+    pub price_conf: u64,            // Last refreshed oracle confidence
+    pub price_exponent: i32,        // Last refreshed oracle exponent
+    pub last_update_slot: u64,      // Slot of the last refresh_reserve
+    pub flash_loan_amount: u64,     // Outstanding flash loan (0 when none is in flight)
+}
+
+/// Mint features that let a third party freeze or move tokens held by the reserve.
+/// add_reserve rejects them unless the authority opts in, e.g. for a regulated stablecoin.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct MintRiskOverrides {
+    pub allow_freeze_authority: bool,   // Freeze authority, default-frozen accounts or pausable mints
+    pub allow_permanent_delegate: bool, // Token-2022 permanent delegate
+}
+
+/// Where a reserve's price comes from.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum OracleSource {
+    Pyth { feed_id: [u8; 32] },          // PriceUpdateV2 posted by the Pyth receiver
+    Switchboard { feed: Pubkey },        // Switchboard On-Demand pull feed account
+    Fixed { price: i64, exponent: i32 }, // Constant price, for stable assets and testing
+    StakePool { feed_id: [u8; 32], stake_pool: Pubkey }, // LST: base Pyth price x stake pool exchange rate
+}
+
+/// Primary oracle plus an optional secondary that the primary must agree with.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct OracleConfig {
+    pub primary: OracleSource,
+    pub secondary: Option<OracleSource>,
+    pub max_divergence_bps: u16,    // Max primary/secondary gap as a fraction of primary
+}
+
+// An SPL stake pool's exchange rate: total_lamports / pool_token_supply lamports per pool token
+struct StakePoolRate {
+    total_lamports: u64,
+    pool_token_supply: u64,
+}
+
+// A price normalized from any oracle source: price x 10^exponent, conf in the same units
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+}
+
+/// Risk parameters of a reserve. All ratios are basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct ReserveConfig {
+    pub ltv_bps: u16,               // Max borrow power per unit of collateral value
     pub liquidation_threshold: u16, // In basis points (8000 = 80%)
     pub liquidation_bonus_bps: u16, // In basis points (500 = 5%)
+    pub interest_rate_model: InterestRateModel,
+    pub flash_loan_fee_bps: u16,    // Fee on flash loans, left in the vault for suppliers
+    pub reserve_factor_bps: u16,    // Share of borrow interest kept as protocol fees
+    pub debt_ceiling: u64,          // Max total debt in this reserve's tokens (0 = no ceiling)
+    pub isolation: Option<IsolationConfig>, // Set to only back isolated obligations
+    pub emode_category: u8,         // E-mode category of this asset (0 = none)
+}
+
+/// Isolation mode for a risky collateral asset: obligations isolated on it may only
+/// borrow one designated reserve, and their combined debt is capped.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct IsolationConfig {
+    pub borrow_reserve: Pubkey,     // The only reserve (e.g. a stable) isolated obligations can borrow
+    pub max_isolated_debt: u64,     // Ceiling on debt backed by this collateral, in borrow_reserve tokens
+}
+
+/// How an obligation's collateral backs its borrows.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ObligationMode {
+    Cross,                                  // Every non-isolated deposit backs every borrow
+    Isolated { collateral_reserve: Pubkey }, // Only this isolated reserve, backing only its designated borrow
+}
+
+/// Kinked utilization-based borrow rate model. All rates are annualized basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct InterestRateModel {
+    pub base_rate_bps: u32,           // Rate at 0% utilization
+    pub slope1_bps: u32,              // Rate added between 0% and optimal utilization
+    pub slope2_bps: u32,              // Rate added between optimal and 100% utilization
+    pub optimal_utilization_bps: u16, // Kink point (8000 = 80%)
 }
 
 #[account]
@@ -297,8 +3284,295 @@ pub struct LendingPool {
 pub struct UserAccount {
     pub owner: Pubkey,
     pub pool: Pubkey,
-    pub collateral_amount: u64, // Amount of collateral deposited
-    pub debt_amount: u64,       // Amount of debt borrowed
+    pub mode: ObligationMode,
+    pub emode_category: u8,         // E-mode category the obligation opted into (0 = none)
+    #[max_len(MAX_OBLIGATION_RESERVES)]
+    pub deposits: Vec<ObligationDeposit>,
+    #[max_len(MAX_OBLIGATION_RESERVES)]
+    pub borrows: Vec<ObligationBorrow>,
+    pub collateral_value: u128,     // Deposit value at the last refresh_obligation
+    pub debt_value: u128,           // Borrow value at the last refresh_obligation
+    pub health_factor: u128,        // Health factor at the last refresh (u128::MAX without debt)
+    pub last_refresh_slot: u64,     // Slot of the last refresh_obligation
+}
+
+/// Snapshot of an obligation's health, in PRECISION units.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ObligationHealth {
+    pub collateral_value: u128,
+    pub borrow_limit: u128,
+    pub liquidation_limit: u128,
+    pub debt_value: u128,
+    pub health_factor: u128,
+    pub slot: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct ObligationDeposit {
+    pub reserve: Pubkey,
+    pub amount: u64,                // Amount of collateral deposited
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct ObligationBorrow {
+    pub reserve: Pubkey,
+    pub debt_shares: u64,           // Scaled debt; debt owed = debt_shares * borrow_index
+}
+
+// Events
+
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub max_price_age_secs: u64,
+    pub max_confidence_bps: u16,
+    pub close_factor_bps: u16,
+    pub full_liquidation_hf_bps: u16,
+    pub max_liquidation_bonus_bps: u16,
+}
+
+#[event]
+pub struct ReserveAdded {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub token_program: Pubkey,
+    pub mint_risks: MintRiskOverrides,
+    pub oracle: OracleConfig,
+    pub config: ReserveConfig,
+}
+
+#[event]
+pub struct Supplied {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub receipts: u64,
+    pub supply_index: u128,
+}
+
+#[event]
+pub struct Redeemed {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub receipts: u64,
+    pub supply_index: u128,
+}
+
+#[event]
+pub struct ProtocolFeesWithdrawn {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub protocol_fees: u64,
+}
+
+#[event]
+pub struct PoolConfigUpdated {
+    pub pool: Pubkey,
+    pub config: PoolConfig,
+}
+
+#[event]
+pub struct EModeCategoryUpdated {
+    pub pool: Pubkey,
+    pub category: EModeCategory,
+}
+
+#[event]
+pub struct ObligationEModeSet {
+    pub pool: Pubkey,
+    pub user_account: Pubkey,
+    pub emode_category: u8,
+}
+
+#[event]
+pub struct ReserveConfigUpdated {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub config: ReserveConfig,
+}
+
+#[event]
+pub struct ReserveOracleUpdated {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub old_oracle: OracleConfig,
+    pub new_oracle: OracleConfig,
+}
+
+#[event]
+pub struct PauseFlagsUpdated {
+    pub pool: Pubkey,
+    pub signer: Pubkey,
+    pub pause_flags: PauseFlags,
+}
+
+#[event]
+pub struct GuardianUpdated {
+    pub pool: Pubkey,
+    pub old_guardian: Pubkey,
+    pub new_guardian: Pubkey,
+}
+
+#[event]
+pub struct SwapProgramUpdated {
+    pub pool: Pubkey,
+    pub old_swap_program: Pubkey,
+    pub new_swap_program: Pubkey,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub pool: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct UserInitialized {
+    pub pool: Pubkey,
+    pub user_account: Pubkey,
+    pub owner: Pubkey,
+    pub mode: ObligationMode,
+}
+
+#[event]
+pub struct ReserveRefreshed {
+    pub reserve: Pubkey,
+    pub price: i64,
+    pub price_conf: u64,
+    pub price_exponent: i32,
+    pub borrow_index: u128,
+    pub supply_index: u128,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ObligationRefreshed {
+    pub pool: Pubkey,
+    pub user_account: Pubkey,
+    pub health: ObligationHealth,
+}
+
+#[event]
+pub struct Deposited {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user_account: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct Borrowed {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user_account: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub debt_shares: u64,   // Shares minted for this borrow
+    pub borrow_index: u128,
+    pub debt_value: u128,   // Obligation debt value after the borrow
+    pub borrow_limit: u128,
+}
+
+#[event]
+pub struct Repaid {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user_account: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub shares_burned: u64,
+    pub borrow_index: u128,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user_account: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct Liquidated {
+    pub pool: Pubkey,
+    pub user_account: Pubkey,
+    pub liquidator: Pubkey,
+    pub repay_reserve: Pubkey,
+    pub withdraw_reserve: Pubkey,
+    pub repay_price: i64,           // Cached mid prices used for the seize calculation
+    pub repay_price_exponent: i32,
+    pub withdraw_price: i64,
+    pub withdraw_price_exponent: i32,
+    pub health_factor: u128,        // Before liquidation, scaled by PRECISION
+    pub liquidation_bonus_bps: u16,
+    pub debt_repaid: u64,
+    pub collateral_seized: u64,
+}
+
+#[event]
+pub struct CollateralSwapped {
+    pub pool: Pubkey,
+    pub user_account: Pubkey,
+    pub liquidator: Pubkey,
+    pub swap_program: Pubkey,
+    pub collateral_in: u64,         // Seized collateral sent into the swap
+    pub proceeds: u64,              // Debt tokens received from the swap
+    pub profit: u64,                // Proceeds left to the liquidator after the repayment
+}
+
+#[event]
+pub struct FlashBorrowed {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FlashRepaid {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct InsuranceFunded {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub insurance_fund: u64, // Balance after funding
+}
+
+#[event]
+pub struct BadDebtWrittenOff {
+    pub pool: Pubkey,
+    pub reserve: Pubkey,
+    pub user_account: Pubkey,
+    pub debt_written_off: u64,     // Debt removed from the obligation
+    pub covered_by_insurance: u64, // Part absorbed by the reserve's insurance fund
+    pub socialized: u64,           // Part spread across suppliers
+    pub supply_index: u128,        // Supply index after the write-off
 }
 
 #[error_code]
@@ -315,5 +3589,325 @@ pub enum ErrorCode {
     InvalidFeedId,
     #[msg("User has no debt")]
     NoDebt,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Insufficient liquidity in the reserve vault")]
+    InsufficientLiquidity,
+    #[msg("LTV must be non-zero and below the liquidation threshold")]
+    InvalidMaxLtv,
+    #[msg("Borrow would exceed the obligation's borrow limit")]
+    MaxLtvExceeded,
+    #[msg("Optimal utilization must be between 0% and 100% exclusive")]
+    InvalidInterestRateModel,
+    #[msg("Oracle price is older than the pool's max price age")]
+    StalePrice,
+    #[msg("Oracle price must be positive")]
+    NonPositivePrice,
+    #[msg("Oracle price exponent is out of range")]
+    InvalidPriceExponent,
+    #[msg("Oracle confidence interval is too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Invalid oracle price update")]
+    InvalidPriceUpdate,
+    #[msg("Reserve risk parameters are out of range")]
+    InvalidReserveConfig,
+    #[msg("Pool cannot list more reserves")]
+    TooManyReserves,
+    #[msg("Obligation has no free deposit or borrow slot")]
+    ObligationFull,
+    #[msg("Reserve account does not belong to this pool")]
+    InvalidReserve,
+    #[msg("Reserve was not refreshed in the current slot")]
+    ReserveStale,
+    #[msg("A reserve referenced by the obligation was not provided")]
+    MissingReserve,
+    #[msg("Repay and withdraw reserves must differ")]
+    InvalidLiquidationReserves,
+    #[msg("Obligation still holds collateral")]
+    ObligationHasCollateral,
+    #[msg("Invalid pool configuration")]
+    InvalidPoolConfig,
+    #[msg("Signer is not authorized for this action")]
+    Unauthorized,
+    #[msg("Oracle account is not owned by the expected program")]
+    InvalidOracleOwner,
+    #[msg("Price update is not fully verified")]
+    PriceNotFullyVerified,
+    #[msg("Invalid oracle configuration")]
+    InvalidOracleConfig,
+    #[msg("Secondary oracle account is required for this reserve")]
+    MissingSecondaryOracle,
+    #[msg("Primary and secondary oracle prices diverge too much")]
+    OracleDivergence,
+    #[msg("A flash loan is already in flight for this reserve")]
+    FlashLoanActive,
+    #[msg("No matching flash loan is in flight for this reserve")]
+    FlashLoanNotActive,
+    #[msg("Flash borrow has no matching flash repay later in the transaction")]
+    FlashRepayMissing,
+    #[msg("Flash loans cannot be invoked through CPI")]
+    FlashLoanCpi,
+    #[msg("Deposits are paused")]
+    DepositsPaused,
+    #[msg("Borrows are paused")]
+    BorrowsPaused,
+    #[msg("Withdrawals are paused")]
+    WithdrawalsPaused,
+    #[msg("Liquidations are paused")]
+    LiquidationsPaused,
+    #[msg("Mint decimals are above the supported maximum")]
+    UnsupportedMintDecimals,
+    #[msg("Amount exceeds the reserve's accrued protocol fees")]
+    InsufficientProtocolFees,
+    #[msg("Borrow would exceed the reserve's debt ceiling")]
+    DebtCeilingExceeded,
+    #[msg("Borrow would exceed the isolated collateral's debt ceiling")]
+    IsolatedDebtCeilingExceeded,
+    #[msg("Isolated obligations hold only their collateral reserve and borrow only its designated reserve")]
+    IsolationViolation,
+    #[msg("Isolated reserves can only back isolated obligations")]
+    IsolatedCollateral,
+    #[msg("Reserve is not configured for isolation")]
+    NotIsolatedReserve,
+    #[msg("Isolated obligations must pass their collateral reserve")]
+    MissingIsolatedReserve,
+    #[msg("E-mode category does not exist in this pool")]
+    UnknownEModeCategory,
+    #[msg("Pool already has the maximum number of e-mode categories")]
+    TooManyEModeCategories,
+    #[msg("Invalid e-mode category parameters")]
+    InvalidEModeCategory,
+    #[msg("Obligations in e-mode can only borrow assets of their category")]
+    EModeViolation,
+    #[msg("Stake pool sources need the stake pool account")]
+    MissingStakePool,
+    #[msg("Stake pool account is not the configured SPL stake pool for this mint")]
+    InvalidStakePool,
+    #[msg("Stake pool has not been updated this epoch")]
+    StaleStakePool,
+    #[msg("Mint can freeze token accounts; the pool authority must allow it explicitly")]
+    MintFreezeAuthorityNotAllowed,
+    #[msg("Mint has a permanent delegate; the pool authority must allow it explicitly")]
+    MintPermanentDelegateNotAllowed,
+    #[msg("Mint has a Token-2022 extension reserves do not support")]
+    UnsupportedMintExtension,
+    #[msg("Pool has no swap program configured")]
+    SwapProgramNotSet,
+    #[msg("Swap program is not the pool's configured swap program")]
+    InvalidSwapProgram,
+    #[msg("Swap proceeds do not cover the repayment plus the minimum profit")]
+    InsufficientSwapProceeds,
+    #[msg("Seized collateral was not fully swapped")]
+    CollateralNotSwapped,
+    #[msg("Isolation mode or borrow reserve cannot change while the reserve has deposits or isolated debt")]
+    IsolationModeInUse,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2% base, +4% up to the 80% kink, +60% from there to 100% utilization
+    const MODEL: InterestRateModel = InterestRateModel {
+        base_rate_bps: 200,
+        slope1_bps: 400,
+        slope2_bps: 6000,
+        optimal_utilization_bps: 8000,
+    };
+
+    // A reserve at index 1.0 with `debt` tokens borrowed and `supply_shares` receipts outstanding
+    fn reserve(debt: u64, supply_shares: u64, reserve_factor_bps: u16) -> Reserve {
+        Reserve {
+            pool: Pubkey::default(),
+            mint: Pubkey::default(),
+            decimals: 6,
+            token_program: Pubkey::default(),
+            vault: Pubkey::default(),
+            receipt_mint: Pubkey::default(),
+            oracle: OracleConfig {
+                primary: OracleSource::Fixed { price: 1, exponent: 0 },
+                secondary: None,
+                max_divergence_bps: 0,
+            },
+            config: ReserveConfig {
+                ltv_bps: 8000,
+                liquidation_threshold: 8500,
+                liquidation_bonus_bps: 500,
+                interest_rate_model: MODEL,
+                flash_loan_fee_bps: 0,
+                reserve_factor_bps,
+                debt_ceiling: 0,
+                isolation: None,
+                emode_category: 0,
+            },
+            borrow_index: INDEX_PRECISION,
+            total_debt_shares: debt,
+            total_collateral: 0,
+            total_supply_shares: supply_shares,
+            insurance_fund: 0,
+            supply_index: INDEX_PRECISION,
+            protocol_fees: 0,
+            isolated_debt: 0,
+            last_accrual_ts: 0,
+            price: 0,
+            price_conf: 0,
+            price_exponent: 0,
+            last_update_slot: 0,
+            flash_loan_amount: 0,
+        }
+    }
+
+    #[test]
+    fn borrow_rate_is_the_base_rate_at_zero_utilization() {
+        assert_eq!(calculate_borrow_rate(&MODEL, 0).unwrap(), 200);
+    }
+
+    #[test]
+    fn borrow_rate_follows_slope1_up_to_the_kink() {
+        // Halfway to the kink adds half of slope1
+        assert_eq!(calculate_borrow_rate(&MODEL, 4000).unwrap(), 400);
+        // At the kink both branches agree on base + slope1
+        assert_eq!(calculate_borrow_rate(&MODEL, 8000).unwrap(), 600);
+    }
+
+    #[test]
+    fn borrow_rate_follows_slope2_above_the_kink() {
+        // Halfway from the kink to 100% adds half of slope2
+        assert_eq!(calculate_borrow_rate(&MODEL, 9000).unwrap(), 3600);
+        assert_eq!(calculate_borrow_rate(&MODEL, 10000).unwrap(), 6600);
+    }
+
+    #[test]
+    fn a_year_at_the_kink_grows_the_index_by_the_kink_rate() {
+        // 800 of 1000 tokens borrowed: 80% utilization, 6% a year
+        let mut reserve = reserve(800_000_000, 1_000_000_000, 1000);
+        accrue_interest(&mut reserve, 200_000_000, SECONDS_PER_YEAR).unwrap();
+
+        assert_eq!(reserve.borrow_index, INDEX_PRECISION * 106 / 100);
+        assert_eq!(reserve.last_accrual_ts, SECONDS_PER_YEAR);
+
+        // 48 of interest: the 10% reserve factor keeps 4.8, suppliers' receipts gain 43.2 / 1000
+        assert_eq!(reserve.protocol_fees, 4_800_000);
+        assert_eq!(reserve.supply_index, INDEX_PRECISION + INDEX_PRECISION * 432 / 10000);
+    }
+
+    #[test]
+    fn full_utilization_accrues_at_the_top_of_slope2() {
+        let mut reserve = reserve(1_000_000_000, 1_000_000_000, 0);
+        accrue_interest(&mut reserve, 0, SECONDS_PER_YEAR).unwrap();
+
+        assert_eq!(reserve.borrow_index, INDEX_PRECISION * 166 / 100);
+        assert_eq!(reserve.protocol_fees, 0);
+        assert_eq!(reserve.supply_index, INDEX_PRECISION * 166 / 100);
+    }
+
+    #[test]
+    fn index_growth_rounds_up_over_a_single_second() {
+        // One second at 6% is 1.9e9 per 1e18 of index, rounded up
+        let mut reserve = reserve(800_000_000, 1_000_000_000, 0);
+        accrue_interest(&mut reserve, 200_000_000, 1).unwrap();
+
+        let growth = (INDEX_PRECISION * 600).div_ceil(10000 * SECONDS_PER_YEAR as u128);
+        assert_eq!(reserve.borrow_index, INDEX_PRECISION + growth);
+    }
+
+    #[test]
+    fn nothing_accrues_without_debt_or_elapsed_time() {
+        // Zero utilization: no debt, no interest, but the clock still moves
+        let mut idle = reserve(0, 1_000_000_000, 1000);
+        accrue_interest(&mut idle, 1_000_000_000, SECONDS_PER_YEAR).unwrap();
+        assert_eq!(idle.borrow_index, INDEX_PRECISION);
+        assert_eq!(idle.supply_index, INDEX_PRECISION);
+        assert_eq!(idle.last_accrual_ts, SECONDS_PER_YEAR);
+
+        // Same timestamp twice: the second call is a no-op
+        let mut busy = reserve(800_000_000, 1_000_000_000, 1000);
+        accrue_interest(&mut busy, 200_000_000, 0).unwrap();
+        assert_eq!(busy.borrow_index, INDEX_PRECISION);
+        assert_eq!(busy.protocol_fees, 0);
+    }
+
+    // An SPL StakePool account serialized field by field, up to last_update_epoch
+    fn stake_pool_data(pool_mint: &Pubkey, total_lamports: u64, pool_token_supply: u64, epoch: u64) -> Vec<u8> {
+        let mut data = vec![STAKE_POOL_ACCOUNT_TYPE];
+        data.extend_from_slice(&[0; 3 * 32]); // manager, staker, stake_deposit_authority
+        data.push(255); // stake_withdraw_bump_seed
+        data.extend_from_slice(&[0; 2 * 32]); // validator_list, reserve_stake
+        data.extend_from_slice(pool_mint.as_ref());
+        data.extend_from_slice(&[0; 2 * 32]); // manager_fee_account, token_program_id
+        data.extend_from_slice(&total_lamports.to_le_bytes());
+        data.extend_from_slice(&pool_token_supply.to_le_bytes());
+        data.extend_from_slice(&epoch.to_le_bytes());
+        data
+    }
+
+    fn read_rate(data: &mut [u8], owner: &Pubkey, mint: &Pubkey, epoch: u64) -> Result<StakePoolRate> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, data, owner, false, 0);
+        let clock = Clock { epoch, ..Clock::default() };
+        read_stake_pool_rate(&info, &key, mint, &clock)
+    }
+
+    #[test]
+    fn stake_pool_fields_are_read_at_their_layout_offsets() {
+        let mint = Pubkey::new_unique();
+        let mut data = stake_pool_data(&mint, 1_100_000_000, 1_000_000_000, 7);
+        assert_eq!(data.len(), STAKE_POOL_MIN_LEN);
+
+        let rate = read_rate(&mut data, &SPL_STAKE_POOL_PROGRAM_ID, &mint, 7).unwrap();
+        assert_eq!(rate.total_lamports, 1_100_000_000);
+        assert_eq!(rate.pool_token_supply, 1_000_000_000);
+    }
+
+    #[test]
+    fn stake_pool_is_refused_unless_owned_current_and_issuing_the_mint() {
+        let mint = Pubkey::new_unique();
+        let mut data = stake_pool_data(&mint, 1_100_000_000, 1_000_000_000, 7);
+        let stake_pool_error = |result: Result<StakePoolRate>| result.err().unwrap();
+
+        assert_eq!(
+            stake_pool_error(read_rate(&mut data, &Pubkey::new_unique(), &mint, 7)),
+            ErrorCode::InvalidStakePool.into()
+        );
+        assert_eq!(
+            stake_pool_error(read_rate(&mut data, &SPL_STAKE_POOL_PROGRAM_ID, &Pubkey::new_unique(), 7)),
+            ErrorCode::InvalidStakePool.into()
+        );
+        assert_eq!(
+            stake_pool_error(read_rate(&mut data, &SPL_STAKE_POOL_PROGRAM_ID, &mint, 8)),
+            ErrorCode::StaleStakePool.into()
+        );
+        assert_eq!(
+            stake_pool_error(read_rate(&mut data[..STAKE_POOL_MIN_LEN - 1], &SPL_STAKE_POOL_PROGRAM_ID, &mint, 7)),
+            ErrorCode::InvalidStakePool.into()
+        );
+    }
+
+    #[test]
+    fn stake_pool_with_no_pool_tokens_is_refused() {
+        let mint = Pubkey::new_unique();
+        let mut data = stake_pool_data(&mint, 1_100_000_000, 0, 7);
+        assert_eq!(
+            read_rate(&mut data, &SPL_STAKE_POOL_PROGRAM_ID, &mint, 7).err().unwrap(),
+            ErrorCode::InvalidStakePool.into()
+        );
+    }
+
+    #[test]
+    fn stake_pool_price_is_the_base_price_times_lamports_per_pool_token() {
+        // SOL/USD at $150.00 ± $0.10 and 1.1 SOL per pool token: $165.00 ± $0.11
+        let sol = OraclePrice { price: 15_000_000_000, conf: 10_000_000, exponent: -8 };
+        let rate = StakePoolRate { total_lamports: 1_100_000_000, pool_token_supply: 1_000_000_000 };
+        let lst = apply_stake_pool_rate(&sol, &rate).unwrap();
+        assert_eq!((lst.price, lst.conf, lst.exponent), (16_500_000_000, 11_000_000, -8));
+
+        // At 4/3 SOL per pool token the price rounds down and the confidence up
+        let rate = StakePoolRate { total_lamports: 4, pool_token_supply: 3 };
+        let lst = apply_stake_pool_rate(&sol, &rate).unwrap();
+        assert_eq!((lst.price, lst.conf), (20_000_000_000, 13_333_334));
+        let sol = OraclePrice { price: 100, conf: 0, exponent: -8 };
+        assert_eq!(apply_stake_pool_rate(&sol, &rate).unwrap().price, 133);
+    }
+}
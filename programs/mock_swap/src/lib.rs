@@ -0,0 +1,211 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("7HpE6PsmgGknvbL8aeW6giYAYVufRSHAe9xwLsNQxdor");
+
+// Constants for the constant-product curve
+const BPS_DENOMINATOR: u128 = 10_000;
+const MAX_FEE_BPS: u16 = 1000; // 10% cap on the swap fee
+
+/// Minimal constant-product (x * y = k) pool used by atomliq's local tests of `liquidate_and_swap`.
+/// Liquidity is added by minting straight into the pool's vaults.
+#[program]
+pub mod mock_swap {
+    use super::*;
+
+    /// Create a pool for a pair of mints, with a vault for each side
+    pub fn initialize_swap_pool(ctx: Context<InitializeSwapPool>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, SwapErrors::InvalidFee);
+
+        let swap_pool = &mut ctx.accounts.swap_pool;
+        swap_pool.mint_a = ctx.accounts.mint_a.key();
+        swap_pool.mint_b = ctx.accounts.mint_b.key();
+        swap_pool.vault_a = ctx.accounts.vault_a.key();
+        swap_pool.vault_b = ctx.accounts.vault_b.key();
+        swap_pool.fee_bps = fee_bps;
+        swap_pool.bump = ctx.bumps.swap_pool;
+
+        msg!("Swap pool initialized, fee {} bps", fee_bps);
+        Ok(())
+    }
+
+    /// Swap `amount_in` of one side of the pool for the other along x * y = k
+    /// The fee stays in the pool; fails if the output is below `minimum_amount_out`
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, minimum_amount_out: u64) -> Result<()> {
+        require!(amount_in > 0, SwapErrors::InvalidAmount);
+
+        // 1. Quote against the vault balances before the trade
+        let amount_out = quote(
+            amount_in,
+            ctx.accounts.source_vault.amount,
+            ctx.accounts.destination_vault.amount,
+            ctx.accounts.swap_pool.fee_bps,
+        )?;
+        require!(amount_out > 0, SwapErrors::InsufficientLiquidity);
+        require!(amount_out >= minimum_amount_out, SwapErrors::SlippageExceeded);
+
+        // 2. Pull the input from the user
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.source_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_source.to_account_info(),
+                    mint: ctx.accounts.source_mint.to_account_info(),
+                    to: ctx.accounts.source_vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+            ctx.accounts.source_mint.decimals,
+        )?;
+
+        // 3. Pay the output from the pool
+        let swap_pool = &ctx.accounts.swap_pool;
+        let pool_seeds = &[
+            b"swap_pool".as_ref(),
+            swap_pool.mint_a.as_ref(),
+            swap_pool.mint_b.as_ref(),
+            &[swap_pool.bump],
+        ];
+        let pool_signer = &[&pool_seeds[..]];
+
+        anchor_spl::token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.destination_token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.destination_vault.to_account_info(),
+                    mint: ctx.accounts.destination_mint.to_account_info(),
+                    to: ctx.accounts.user_destination.to_account_info(),
+                    authority: ctx.accounts.swap_pool.to_account_info(),
+                },
+                pool_signer,
+            ),
+            amount_out,
+            ctx.accounts.destination_mint.decimals,
+        )?;
+
+        msg!("Swapped {} for {}", amount_in, amount_out);
+        Ok(())
+    }
+}
+
+// Helper function to quote a constant-product swap: out = reserve_out * in / (reserve_in + in),
+// with the fee taken from the input first
+fn quote(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<u64> {
+    let amount_in_after_fee = (amount_in as u128)
+        .checked_mul(BPS_DENOMINATOR - fee_bps as u128)
+        .ok_or(SwapErrors::MathOverflow)?
+        / BPS_DENOMINATOR;
+    let numerator = (reserve_out as u128)
+        .checked_mul(amount_in_after_fee)
+        .ok_or(SwapErrors::MathOverflow)?;
+    let denominator = (reserve_in as u128)
+        .checked_add(amount_in_after_fee)
+        .ok_or(SwapErrors::MathOverflow)?;
+    require!(denominator > 0, SwapErrors::InsufficientLiquidity);
+
+    Ok((numerator / denominator) as u64)
+}
+
+#[derive(Accounts)]
+pub struct InitializeSwapPool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SwapPool::INIT_SPACE,
+        seeds = [b"swap_pool", mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump
+    )]
+    pub swap_pool: Account<'info, SwapPool>,
+    #[account(constraint = mint_a.key() != mint_b.key() @ SwapErrors::IdenticalMints)]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    pub mint_b: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"swap_vault", swap_pool.key().as_ref(), mint_a.key().as_ref()],
+        bump,
+        token::mint = mint_a,
+        token::authority = swap_pool,
+        token::token_program = token_program_a,
+    )]
+    pub vault_a: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"swap_vault", swap_pool.key().as_ref(), mint_b.key().as_ref()],
+        bump,
+        token::mint = mint_b,
+        token::authority = swap_pool,
+        token::token_program = token_program_b,
+    )]
+    pub vault_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    pub swap_pool: Account<'info, SwapPool>,
+    #[account(
+        mut,
+        constraint = (source_vault.key() == swap_pool.vault_a && destination_vault.key() == swap_pool.vault_b)
+            || (source_vault.key() == swap_pool.vault_b && destination_vault.key() == swap_pool.vault_a)
+            @ SwapErrors::InvalidVault
+    )]
+    pub source_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub destination_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = source_vault.mint)]
+    pub source_mint: InterfaceAccount<'info, Mint>,
+    #[account(address = destination_vault.mint)]
+    pub destination_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = source_mint,
+        token::authority = user,
+    )]
+    pub user_source: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = destination_mint,
+    )]
+    pub user_destination: InterfaceAccount<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub source_token_program: Interface<'info, TokenInterface>,
+    pub destination_token_program: Interface<'info, TokenInterface>,
+}
+
+// Data Structures
+
+#[account]
+#[derive(InitSpace)]
+pub struct SwapPool {
+    pub mint_a: Pubkey,              // 32
+    pub mint_b: Pubkey,              // 32
+    pub vault_a: Pubkey,             // 32
+    pub vault_b: Pubkey,             // 32
+    pub fee_bps: u16,                // 2
+    pub bump: u8,                    // 1
+}
+
+#[error_code]
+pub enum SwapErrors {
+    #[msg("Slippage limit exceeded")]
+    SlippageExceeded,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Insufficient liquidity")]
+    InsufficientLiquidity,
+    #[msg("Swap fee above the maximum")]
+    InvalidFee,
+    #[msg("Pool sides must be different mints")]
+    IdenticalMints,
+    #[msg("Vaults are not the two sides of this pool")]
+    InvalidVault,
+}
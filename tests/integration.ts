@@ -1,14 +1,31 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program, BN } from "@coral-xyz/anchor";
 import { Atomliq } from "../target/types/atomliq";
+import { MockSwap } from "../target/types/mock_swap";
 import { SolFabric, SolFabricConfig } from "../sdk/src";
 import {
+    ComputeBudgetProgram,
     PublicKey,
     Keypair,
     SystemProgram,
+    Transaction,
     TransactionMessage,
     VersionedTransaction,
 } from "@solana/web3.js";
+import {
+    TOKEN_PROGRAM_ID,
+    TOKEN_2022_PROGRAM_ID,
+    ExtensionType,
+    createInitializeMintInstruction,
+    createInitializePermanentDelegateInstruction,
+    createInitializeTransferFeeConfigInstruction,
+    getMintLen,
+    createMint,
+    createAccount,
+    mintTo,
+    getAccount,
+    getMint,
+} from "@solana/spl-token";
 import { assert } from "chai";
 
 describe("SolFabric Integration - Atomliq Liquidation", () => {
@@ -16,6 +33,7 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
     anchor.setProvider(provider);
 
     const program = anchor.workspace.Atomliq as Program<Atomliq>;
+    const mockSwap = anchor.workspace.MockSwap as Program<MockSwap>;
 
     // SDK Setup
     const config: SolFabricConfig = {
@@ -30,10 +48,474 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
     let liquidatorKeypair: Keypair;
     let userAccountPDA: PublicKey;
 
+    // Reserves: SOL-like collateral and USDC-like borrowable liquidity
+    let collateralMint: PublicKey;
+    let debtMint: PublicKey;
+    let collateralReserve: PublicKey;
+    let debtReserve: PublicKey;
+    let collateralReserveVault: PublicKey;
+    let debtReserveVault: PublicKey;
+    let collateralReceiptMint: PublicKey;
+    let debtReceiptMint: PublicKey;
+    let borrowerCollateralAccount: PublicKey;
+    let borrowerDebtAccount: PublicKey;
+    let supplierDebtAccount: PublicKey;
+    let supplierReceiptAccount: PublicKey;
+
+    // Liquidity supplied to the debt reserve so borrows can be served
+    const POOL_LIQUIDITY = 1_000_000_000_000; // 1M USDC in 6 decimals
+
     // Pyth feed IDs (using actual Pyth feed IDs for SOL/USD and USDC/USD)
     const SOL_USD_FEED = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d"; // SOL/USD
     const USDC_USD_FEED = "eaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a"; // USDC/USD
 
+    // Kinked borrow rate: 2% base, +8% up to 80% utilization, +100% beyond it
+    const INTEREST_RATE_MODEL = {
+        baseRateBps: 200,
+        slope1Bps: 800,
+        slope2Bps: 10000,
+        optimalUtilizationBps: 8000,
+    };
+
+    const reserveConfig = (ltvBps: number, liquidationThreshold: number) => ({
+        ltvBps,
+        liquidationThreshold,
+        liquidationBonusBps: 500,
+        interestRateModel: INTEREST_RATE_MODEL,
+        flashLoanFeeBps: 30,
+        reserveFactorBps: 1000,
+        debtCeiling: new BN(0),
+        isolation: null,
+        emodeCategory: 0,
+    });
+
+    // Mints in these tests have no freeze authority or permanent delegate to opt into
+    const NO_MINT_RISKS = { allowFreezeAuthority: false, allowPermanentDelegate: false };
+
+    const feedIdBytes = (hex: string) => Array.from(Buffer.from(hex, "hex"));
+
+    // Oracle configs: a Pyth feed alone, or fixed prices for tests that need no price updates
+    const pythOracle = (hex: string) => ({
+        primary: { pyth: { feedId: feedIdBytes(hex) } },
+        secondary: null,
+        maxDivergenceBps: 0,
+    });
+    const fixedSource = (price: number, exponent: number) => ({
+        fixed: { price: new BN(price), exponent },
+    });
+
+    const deriveReserve = (
+        mint: PublicKey,
+        pool: PublicKey = poolKeypair.publicKey
+    ): [PublicKey, PublicKey, PublicKey] => {
+        const [reserve] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reserve"), pool.toBuffer(), mint.toBuffer()],
+            program.programId
+        );
+        const [vault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("reserve_vault"), reserve.toBuffer()],
+            program.programId
+        );
+        const [receiptMint] = PublicKey.findProgramAddressSync(
+            [Buffer.from("receipt_mint"), reserve.toBuffer()],
+            program.programId
+        );
+        return [reserve, vault, receiptMint];
+    };
+
+    // Reserves priced by fixed oracle sources (in cents), for tests that need no price updates
+    type FixedReserve = {
+        pool: PublicKey;
+        mint: PublicKey;
+        reserve: PublicKey;
+        vault: PublicKey;
+        receiptMint: PublicKey;
+        tokenProgram: PublicKey;
+    };
+    const createFixedMint = () =>
+        createMint(provider.connection, provider.wallet.payer, provider.wallet.publicKey, null, 6);
+    const addFixedReserve = async (
+        mint: PublicKey,
+        cents: number,
+        config: ReturnType<typeof reserveConfig>,
+        pool: PublicKey = poolKeypair.publicKey,
+        tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+    ): Promise<FixedReserve> => {
+        const [reserve, vault, receiptMint] = deriveReserve(mint, pool);
+        await program.methods
+            .addReserve({ primary: fixedSource(cents, -2), secondary: null, maxDivergenceBps: 0 }, config, NO_MINT_RISKS)
+            .accounts({
+                pool,
+                reserve,
+                mint,
+                reserveVault: vault,
+                receiptMint,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+                tokenProgram,
+            })
+            .rpc();
+        return { pool, mint, reserve, vault, receiptMint, tokenProgram };
+    };
+    // refresh_reserve must land in the same slot as any instruction that prices the obligation
+    const refreshFixedReserves = (reserves: FixedReserve[]) =>
+        Promise.all(
+            reserves.map((r) =>
+                program.methods
+                    .refreshReserve()
+                    .accounts({
+                        pool: r.pool,
+                        reserve: r.reserve,
+                        reserveVault: r.vault,
+                        oracle: r.reserve, // Unused by fixed-price sources
+                        secondaryOracle: null,
+                        stakePool: null,
+                    })
+                    .instruction()
+            )
+        );
+    // Supply liquidity from the provider wallet so the reserve can be borrowed from
+    // The supplier's accounts are keypair accounts, leaving the wallet's ATAs to its obligations
+    const supplyFixedReserve = async (r: FixedReserve, amount: number) => {
+        const supplierToken = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            r.mint,
+            provider.wallet.publicKey,
+            Keypair.generate(),
+            undefined,
+            r.tokenProgram
+        );
+        const supplierReceipt = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            r.receiptMint,
+            provider.wallet.publicKey,
+            Keypair.generate(),
+            undefined,
+            r.tokenProgram
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            r.mint,
+            supplierToken,
+            provider.wallet.publicKey,
+            amount,
+            [],
+            undefined,
+            r.tokenProgram
+        );
+        await program.methods
+            .supply(new BN(amount))
+            .accounts({
+                pool: r.pool,
+                reserve: r.reserve,
+                reserveVault: r.vault,
+                mint: r.mint,
+                receiptMint: r.receiptMint,
+                userToken: supplierToken,
+                userReceipt: supplierReceipt,
+                owner: provider.wallet.publicKey,
+                tokenProgram: r.tokenProgram,
+            })
+            .rpc();
+    };
+    // A pool of its own, for tests that need the default pool config or more reserves than the main pool holds
+    const createFixedPool = async () => {
+        const pool = Keypair.generate();
+        await program.methods
+            .initializePool()
+            .accounts({
+                pool: pool.publicKey,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+            })
+            .signers([pool])
+            .rpc();
+        return pool.publicKey;
+    };
+    const repriceFixedReserve = (r: FixedReserve, cents: number) =>
+        program.methods
+            .updateReserveOracle({ primary: fixedSource(cents, -2), secondary: null, maxDivergenceBps: 0 })
+            .accounts({
+                pool: r.pool,
+                reserve: r.reserve,
+                reserveVault: r.vault,
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+    // Open an obligation on two fixed-price reserves, cross-margin unless `isolated`, with token
+    // accounts for both mints and an optional initial deposit. `borrow` refreshes both reserves
+    // and passes them as remaining accounts
+    const openFixedObligation = async (
+        collateral: FixedReserve,
+        debt: FixedReserve,
+        {
+            owner = provider.wallet.payer,
+            deposit = 0,
+            isolated = false,
+        }: { owner?: Keypair; deposit?: number; isolated?: boolean } = {}
+    ) => {
+        const pool = collateral.pool;
+        const seeds = [Buffer.from("user"), owner.publicKey.toBuffer(), pool.toBuffer()];
+        if (isolated) seeds.push(collateral.reserve.toBuffer());
+        const [obligation] = PublicKey.findProgramAddressSync(seeds, program.programId);
+        if (isolated) {
+            await program.methods
+                .initializeIsolatedUser()
+                .accounts({
+                    userAccount: obligation,
+                    pool,
+                    collateralReserve: collateral.reserve,
+                    owner: owner.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([owner])
+                .rpc();
+        } else {
+            await program.methods
+                .initializeUser()
+                .accounts({
+                    userAccount: obligation,
+                    pool,
+                    owner: owner.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([owner])
+                .rpc();
+        }
+
+        const collateralToken = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            collateral.mint,
+            owner.publicKey,
+            undefined,
+            undefined,
+            collateral.tokenProgram
+        );
+        const debtToken = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debt.mint,
+            owner.publicKey,
+            undefined,
+            undefined,
+            debt.tokenProgram
+        );
+        const reserveAccounts = [collateral.reserve, debt.reserve].map((pubkey) => ({
+            pubkey,
+            isSigner: false,
+            isWritable: false,
+        }));
+
+        // Mints `amount` of the collateral to the owner and deposits it
+        const depositCollateral = async (amount: number, userAccount: PublicKey = obligation) => {
+            await mintTo(
+                provider.connection,
+                provider.wallet.payer,
+                collateral.mint,
+                collateralToken,
+                provider.wallet.publicKey,
+                amount,
+                [],
+                undefined,
+                collateral.tokenProgram
+            );
+            return program.methods
+                .depositCollateral(new BN(amount))
+                .accounts({
+                    pool,
+                    reserve: collateral.reserve,
+                    reserveVault: collateral.vault,
+                    mint: collateral.mint,
+                    userAccount,
+                    userCollateral: collateralToken,
+                    owner: owner.publicKey,
+                    tokenProgram: collateral.tokenProgram,
+                })
+                .signers([owner])
+                .rpc();
+        };
+        const borrow = async (amount: number) =>
+            program.methods
+                .borrow(new BN(amount))
+                .accounts({
+                    pool,
+                    reserve: debt.reserve,
+                    reserveVault: debt.vault,
+                    mint: debt.mint,
+                    userAccount: obligation,
+                    userDebt: debtToken,
+                    isolatedReserve: isolated ? collateral.reserve : null,
+                    owner: owner.publicKey,
+                    tokenProgram: debt.tokenProgram,
+                })
+                .remainingAccounts(reserveAccounts)
+                .preInstructions(await refreshFixedReserves([collateral, debt]))
+                .signers([owner])
+                .rpc();
+
+        if (deposit > 0) await depositCollateral(deposit);
+        return { obligation, collateralToken, debtToken, reserveAccounts, depositCollateral, borrow };
+    };
+
+    // Decode the program's events from a confirmed transaction's logs
+    const eventParser = new anchor.EventParser(program.programId, program.coder);
+    const fetchEvents = async (signature: string) => {
+        const tx = await provider.connection.getTransaction(signature, {
+            commitment: "confirmed",
+            maxSupportedTransactionVersion: 0,
+        });
+        return Array.from(eventParser.parseLogs(tx?.meta?.logMessages ?? []));
+    };
+
+    // Debt owed on `shares` at `borrowIndex`, rounded up as the program does
+    const INDEX_PRECISION = new BN("1000000000000000000");
+    const debtFromShares = (shares: BN, borrowIndex: BN) =>
+        shares.mul(borrowIndex).add(INDEX_PRECISION.subn(1)).div(INDEX_PRECISION);
+
+    type LiquidationSnapshot = {
+        liquidatorRepay: BN;
+        liquidatorWithdraw: BN;
+        repayVault: BN;
+        withdrawVault: BN;
+        deposit: BN;
+        debtShares: BN;
+        borrowIndex: BN;
+    };
+    const tokenBalance = async (account: PublicKey, tokenProgram: PublicKey = TOKEN_PROGRAM_ID) =>
+        new BN((await getAccount(provider.connection, account, undefined, tokenProgram)).amount.toString());
+
+    // A fresh pool where the provider wallet deposits `deposit` of a collateral at `config` and
+    // borrows `borrowAmount` of a $1 stable, before the collateral is repriced to `cents`
+    // `liquidate` runs execute_liquidation from the liquidator's own token accounts and returns the
+    // Liquidated event with the balances, deposit and debt shares from either side of it
+    const openUnderwaterObligation = async (
+        config: ReturnType<typeof reserveConfig>,
+        deposit: number,
+        borrowAmount: number,
+        cents: number
+    ) => {
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 100, config, pool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        await supplyFixedReserve(debt, 1_000_000_000);
+
+        const { obligation, reserveAccounts, borrow } = await openFixedObligation(collateral, debt, { deposit });
+        await borrow(borrowAmount);
+        await repriceFixedReserve(collateral, cents);
+
+        const liquidatorRepay = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debt.mint,
+            liquidatorKeypair.publicKey
+        );
+        const liquidatorWithdraw = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            collateral.mint,
+            liquidatorKeypair.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            debt.mint,
+            liquidatorRepay,
+            provider.wallet.publicKey,
+            2 * borrowAmount
+        );
+
+        const snapshot = async (): Promise<LiquidationSnapshot> => {
+            const userAccount = await program.account.userAccount.fetch(obligation);
+            const deposited = userAccount.deposits.find((d) => d.reserve.equals(collateral.reserve));
+            const borrowed = userAccount.borrows.find((b) => b.reserve.equals(debt.reserve));
+            return {
+                liquidatorRepay: await tokenBalance(liquidatorRepay),
+                liquidatorWithdraw: await tokenBalance(liquidatorWithdraw),
+                repayVault: await tokenBalance(debt.vault),
+                withdrawVault: await tokenBalance(collateral.vault),
+                deposit: deposited ? deposited.amount : new BN(0),
+                debtShares: borrowed ? borrowed.debtShares : new BN(0),
+                borrowIndex: (await program.account.reserve.fetch(debt.reserve)).borrowIndex,
+            };
+        };
+
+        const liquidate = async (amount: number) => {
+            const before = await snapshot();
+            const signature = await program.methods
+                .executeLiquidation(new BN(amount))
+                .accounts({
+                    pool,
+                    userAccount: obligation,
+                    repayReserve: debt.reserve,
+                    repayVault: debt.vault,
+                    repayMint: debt.mint,
+                    withdrawReserve: collateral.reserve,
+                    withdrawVault: collateral.vault,
+                    withdrawMint: collateral.mint,
+                    liquidatorRepay,
+                    liquidatorWithdraw,
+                    liquidator: liquidatorKeypair.publicKey,
+                    repayTokenProgram: TOKEN_PROGRAM_ID,
+                    withdrawTokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .remainingAccounts(reserveAccounts)
+                .preInstructions(await refreshFixedReserves([collateral, debt]))
+                .signers([liquidatorKeypair])
+                .rpc({ commitment: "confirmed" });
+            const after = await snapshot();
+            const liquidated = (await fetchEvents(signature)).find((event) => event.name === "liquidated").data;
+            return { liquidated, before, after };
+        };
+
+        return { pool, collateral, debt, obligation, liquidate };
+    };
+
+    // The repayment moves from the liquidator into the debt vault, and the seized collateral from
+    // the collateral vault and the obligation's deposit to the liquidator
+    const assertLiquidationTransfers = (
+        before: LiquidationSnapshot,
+        after: LiquidationSnapshot,
+        liquidated: { debtRepaid: BN; collateralSeized: BN }
+    ) => {
+        const { debtRepaid, collateralSeized } = liquidated;
+        assert.equal(before.liquidatorRepay.sub(after.liquidatorRepay).toString(), debtRepaid.toString());
+        assert.equal(after.repayVault.sub(before.repayVault).toString(), debtRepaid.toString());
+        assert.equal(after.liquidatorWithdraw.sub(before.liquidatorWithdraw).toString(), collateralSeized.toString());
+        assert.equal(before.withdrawVault.sub(after.withdrawVault).toString(), collateralSeized.toString());
+        assert.equal(before.deposit.sub(after.deposit).toString(), collateralSeized.toString());
+    };
+
+    // The repayment burns debt shares at the reserve's current borrow index (all of them once the
+    // debt is covered), and the seizure is the repaid value plus the bonus, at the collateral's
+    // price and rounded down. Both reserves hold 6-decimal tokens priced in cents
+    const assertLiquidationSettlement = (
+        before: LiquidationSnapshot,
+        after: LiquidationSnapshot,
+        liquidated: {
+            debtRepaid: BN;
+            collateralSeized: BN;
+            repayPrice: BN;
+            withdrawPrice: BN;
+            liquidationBonusBps: number;
+        }
+    ) => {
+        const { debtRepaid, collateralSeized, repayPrice, withdrawPrice, liquidationBonusBps } = liquidated;
+        const debtAmount = debtFromShares(before.debtShares, after.borrowIndex);
+        const expectedBurn = debtRepaid.gte(debtAmount)
+            ? before.debtShares
+            : debtRepaid.mul(INDEX_PRECISION).div(after.borrowIndex);
+        assert.equal(before.debtShares.sub(after.debtShares).toString(), expectedBurn.toString());
+
+        const repayValue = debtRepaid.mul(repayPrice).divn(100);
+        const seizeValue = repayValue.muln(10000 + liquidationBonusBps).divn(10000);
+        assert.equal(collateralSeized.toString(), seizeValue.muln(100).div(withdrawPrice).toString());
+    };
+
     before(async () => {
         // Initialize keypairs
         poolKeypair = Keypair.generate();
@@ -53,6 +535,47 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
         // Wait for confirmations
         await new Promise(resolve => setTimeout(resolve, 2000));
 
+        // Create collateral (SOL-like, 9 decimals) and debt (USDC-like, 6 decimals) mints
+        collateralMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        debtMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+
+        [collateralReserve, collateralReserveVault, collateralReceiptMint] = deriveReserve(collateralMint);
+        [debtReserve, debtReserveVault, debtReceiptMint] = deriveReserve(debtMint);
+
+        borrowerCollateralAccount = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            collateralMint,
+            borrowerKeypair.publicKey
+        );
+        borrowerDebtAccount = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            borrowerKeypair.publicKey
+        );
+
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            collateralMint,
+            borrowerCollateralAccount,
+            provider.wallet.publicKey,
+            20_000_000_000 // 20 SOL
+        );
+
         console.log("Borrower:", borrowerKeypair.publicKey.toBase58());
         console.log("Liquidator:", liquidatorKeypair.publicKey.toBase58());
     });
@@ -60,26 +583,740 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
     it("Initializes lending pool", async () => {
         console.log("\n=== Initializing Lending Pool ===");
 
-        await program.methods
-            .initializePool(SOL_USD_FEED, USDC_USD_FEED)
+        const signature = await program.methods
+            .initializePool()
             .accounts({
                 pool: poolKeypair.publicKey,
                 authority: provider.wallet.publicKey,
                 systemProgram: SystemProgram.programId,
             })
             .signers([poolKeypair])
-            .rpc();
+            .rpc({ commitment: "confirmed" });
 
         const poolAccount = await program.account.lendingPool.fetch(
             poolKeypair.publicKey
         );
 
         console.log("Pool initialized at:", poolKeypair.publicKey.toBase58());
-        console.log("Liquidation Threshold:", poolAccount.liquidationThreshold, "bps");
-        console.log("Liquidation Bonus:", poolAccount.liquidationBonusBps, "bps");
+        assert.ok(poolAccount.authority.equals(provider.wallet.publicKey));
+        assert.equal(poolAccount.reserves.length, 0);
+        assert.equal(poolAccount.maxPriceAgeSecs.toNumber(), 60);
+        assert.equal(poolAccount.maxConfidenceBps, 200);
+        assert.equal(poolAccount.closeFactorBps, 5000);
+        assert.equal(poolAccount.fullLiquidationHfBps, 9500);
+        assert.equal(poolAccount.maxLiquidationBonusBps, 1500);
+
+        const [event] = await fetchEvents(signature);
+        assert.equal(event.name, "poolInitialized");
+        assert.ok(event.data.pool.equals(poolKeypair.publicKey));
+        assert.equal(event.data.closeFactorBps, 5000);
+    });
+
+    it("Adds collateral and debt reserves", async () => {
+        console.log("\n=== Adding Reserves ===");
+
+        await program.methods
+            .addReserve(pythOracle(SOL_USD_FEED), reserveConfig(7500, 8000), NO_MINT_RISKS)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: collateralReserve,
+                mint: collateralMint,
+                reserveVault: collateralReserveVault,
+                receiptMint: collateralReceiptMint,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        await program.methods
+            .addReserve(pythOracle(USDC_USD_FEED), reserveConfig(8000, 8500), NO_MINT_RISKS)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: debtReserve,
+                mint: debtMint,
+                reserveVault: debtReserveVault,
+                receiptMint: debtReceiptMint,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        const poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        const reserveAccount = await program.account.reserve.fetch(collateralReserve);
+
+        console.log("Collateral reserve:", collateralReserve.toBase58());
+        console.log("Debt reserve:", debtReserve.toBase58());
+        console.log("Liquidation Threshold:", reserveAccount.config.liquidationThreshold, "bps");
+        console.log("Liquidation Bonus:", reserveAccount.config.liquidationBonusBps, "bps");
+
+        assert.equal(poolAccount.reserves.length, 2);
+        assert.ok(reserveAccount.vault.equals(collateralReserveVault));
+        assert.equal(reserveAccount.decimals, 9);
+        assert.equal((await program.account.reserve.fetch(debtReserve)).decimals, 6);
+        assert.deepEqual(reserveAccount.oracle.primary.pyth.feedId, feedIdBytes(SOL_USD_FEED));
+        assert.equal(reserveAccount.config.ltvBps, 7500);
+        assert.equal(reserveAccount.config.liquidationThreshold, 8000);
+        assert.equal(reserveAccount.config.interestRateModel.optimalUtilizationBps, 8000);
+        assert.equal(reserveAccount.insuranceFund.toNumber(), 0);
+        assert.equal(reserveAccount.supplyIndex.toString(), "1000000000000000000");
+        assert.ok(reserveAccount.receiptMint.equals(collateralReceiptMint));
+        assert.equal((await getMint(provider.connection, collateralReceiptMint)).decimals, 9);
+    });
+
+    it("Supplies liquidity for receipt tokens", async () => {
+        supplierDebtAccount = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            provider.wallet.publicKey
+        );
+        supplierReceiptAccount = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debtReceiptMint,
+            provider.wallet.publicKey
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            supplierDebtAccount,
+            provider.wallet.publicKey,
+            POOL_LIQUIDITY
+        );
+
+        const signature = await program.methods
+            .supply(new BN(POOL_LIQUIDITY))
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: debtReserve,
+                reserveVault: debtReserveVault,
+                mint: debtMint,
+                receiptMint: debtReceiptMint,
+                userToken: supplierDebtAccount,
+                userReceipt: supplierReceiptAccount,
+                owner: provider.wallet.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc({ commitment: "confirmed" });
+
+        // Nothing is borrowed yet, so one receipt is worth one token
+        const receipts = await getAccount(provider.connection, supplierReceiptAccount);
+        const vault = await getAccount(provider.connection, debtReserveVault);
+        const reserveAccount = await program.account.reserve.fetch(debtReserve);
+        assert.equal(receipts.amount.toString(), POOL_LIQUIDITY.toString());
+        assert.equal(vault.amount.toString(), POOL_LIQUIDITY.toString());
+        assert.equal(reserveAccount.totalSupplyShares.toString(), POOL_LIQUIDITY.toString());
+
+        const [event] = await fetchEvents(signature);
+        assert.equal(event.name, "supplied");
+        assert.equal(event.data.receipts.toString(), POOL_LIQUIDITY.toString());
+    });
+
+    it("Rejects an all-zero feed id", async () => {
+        const otherMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+        const [reserve, vault, receiptMint] = deriveReserve(otherMint);
+
+        try {
+            await program.methods
+                .addReserve(pythOracle("00".repeat(32)), reserveConfig(8000, 8500), NO_MINT_RISKS)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve,
+                    mint: otherMint,
+                    reserveVault: vault,
+                    receiptMint,
+                    authority: provider.wallet.publicKey,
+                    systemProgram: SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .rpc();
+            assert.fail("Zero feed id should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidFeedId");
+        }
+    });
+
+    it("Refuses mints that can freeze or seize vault funds unless the admin opts in", async () => {
+        const tryAddReserve = async (mint: PublicKey, tokenProgram: PublicKey) => {
+            const [reserve, vault, receiptMint] = deriveReserve(mint);
+            await program.methods
+                .addReserve(pythOracle(USDC_USD_FEED), reserveConfig(8000, 8500), NO_MINT_RISKS)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve,
+                    mint,
+                    reserveVault: vault,
+                    receiptMint,
+                    authority: provider.wallet.publicKey,
+                    systemProgram: SystemProgram.programId,
+                    tokenProgram,
+                })
+                .rpc();
+        };
+
+        // A freeze authority could lock the reserve vault
+        const freezableMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            provider.wallet.publicKey,
+            6
+        );
+        try {
+            await tryAddReserve(freezableMint, TOKEN_PROGRAM_ID);
+            assert.fail("Freezable mint should need an override");
+        } catch (err) {
+            assert.include(err.toString(), "MintFreezeAuthorityNotAllowed");
+        }
+
+        // A Token-2022 permanent delegate could move tokens out of the vault
+        const delegateMint = Keypair.generate();
+        const mintLen = getMintLen([ExtensionType.PermanentDelegate]);
+        await provider.sendAndConfirm(
+            new Transaction().add(
+                SystemProgram.createAccount({
+                    fromPubkey: provider.wallet.publicKey,
+                    newAccountPubkey: delegateMint.publicKey,
+                    space: mintLen,
+                    lamports: await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+                    programId: TOKEN_2022_PROGRAM_ID,
+                }),
+                createInitializePermanentDelegateInstruction(
+                    delegateMint.publicKey,
+                    provider.wallet.publicKey,
+                    TOKEN_2022_PROGRAM_ID
+                ),
+                createInitializeMintInstruction(
+                    delegateMint.publicKey,
+                    6,
+                    provider.wallet.publicKey,
+                    null,
+                    TOKEN_2022_PROGRAM_ID
+                )
+            ),
+            [delegateMint]
+        );
+        try {
+            await tryAddReserve(delegateMint.publicKey, TOKEN_2022_PROGRAM_ID);
+            assert.fail("Permanent delegate should need an override");
+        } catch (err) {
+            assert.include(err.toString(), "MintPermanentDelegateNotAllowed");
+        }
+    });
+
+    it("Credits deposits net of the transfer fee and grosses up repayments", async () => {
+        // Token-2022 mints that withhold 1% of every transfer
+        const createTransferFeeMint = async () => {
+            const mint = Keypair.generate();
+            const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+            await provider.sendAndConfirm(
+                new Transaction().add(
+                    SystemProgram.createAccount({
+                        fromPubkey: provider.wallet.publicKey,
+                        newAccountPubkey: mint.publicKey,
+                        space: mintLen,
+                        lamports: await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+                        programId: TOKEN_2022_PROGRAM_ID,
+                    }),
+                    createInitializeTransferFeeConfigInstruction(
+                        mint.publicKey,
+                        provider.wallet.publicKey,
+                        provider.wallet.publicKey,
+                        100,
+                        BigInt(1_000_000_000_000),
+                        TOKEN_2022_PROGRAM_ID
+                    ),
+                    createInitializeMintInstruction(
+                        mint.publicKey,
+                        6,
+                        provider.wallet.publicKey,
+                        null,
+                        TOKEN_2022_PROGRAM_ID
+                    )
+                ),
+                [mint]
+            );
+            return mint.publicKey;
+        };
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(
+            await createTransferFeeMint(),
+            100,
+            reserveConfig(8000, 8500),
+            pool,
+            TOKEN_2022_PROGRAM_ID
+        );
+        const debt = await addFixedReserve(
+            await createTransferFeeMint(),
+            100,
+            reserveConfig(8000, 8500),
+            pool,
+            TOKEN_2022_PROGRAM_ID
+        );
+        await supplyFixedReserve(debt, 1_000_000_000);
+
+        // Depositing 1000 credits only the 990 that reaches the vault
+        const { obligation, debtToken, borrow } = await openFixedObligation(collateral, debt, {
+            deposit: 1_000_000_000,
+        });
+        const userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.deposits[0].amount.toNumber(), 990_000_000);
+        assert.equal((await program.account.reserve.fetch(collateral.reserve)).totalCollateral.toNumber(), 990_000_000);
+        assert.equal((await tokenBalance(collateral.vault, TOKEN_2022_PROGRAM_ID)).toNumber(), 990_000_000);
+
+        // Repaying 100 pulls 100 plus the fee from the borrower, so the full 100 lands in the vault
+        await borrow(300_000_000);
+        const vaultBefore = await tokenBalance(debt.vault, TOKEN_2022_PROGRAM_ID);
+        const borrowerBefore = await tokenBalance(debtToken, TOKEN_2022_PROGRAM_ID);
+        await program.methods
+            .repay(new BN(100_000_000))
+            .accounts({
+                pool,
+                reserve: debt.reserve,
+                reserveVault: debt.vault,
+                mint: debt.mint,
+                userAccount: obligation,
+                userDebt: debtToken,
+                isolatedReserve: null,
+                owner: provider.wallet.publicKey,
+                tokenProgram: TOKEN_2022_PROGRAM_ID,
+            })
+            .rpc();
+        const vaultAfter = await tokenBalance(debt.vault, TOKEN_2022_PROGRAM_ID);
+        const borrowerAfter = await tokenBalance(debtToken, TOKEN_2022_PROGRAM_ID);
+        assert.equal(vaultAfter.sub(vaultBefore).toNumber(), 100_000_000);
+        assert.isAbove(borrowerBefore.sub(borrowerAfter).toNumber(), 100_000_000);
+    });
+
+    it("Rejects a price update not owned by the Pyth receiver", async () => {
+        // Any account owned by another program (here, the pool itself) must be refused
+        try {
+            await program.methods
+                .refreshReserve()
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve: collateralReserve,
+                    reserveVault: collateralReserveVault,
+                    oracle: poolKeypair.publicKey,
+                    secondaryOracle: null,
+                    stakePool: null,
+                })
+                .rpc();
+            assert.fail("Foreign price update should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidOracleOwner");
+        }
+    });
+
+    it("Refuses non-positive, stale and uncertain prices", async () => {
+        const pool = await createFixedPool();
+
+        // A fixed price must be positive when it is configured
+        for (const cents of [0, -100]) {
+            try {
+                await addFixedReserve(await createFixedMint(), cents, reserveConfig(8000, 8500), pool);
+                assert.fail("Non-positive fixed price should fail");
+            } catch (err) {
+                assert.include(err.toString(), "NonPositivePrice");
+            }
+        }
+
+        // Pyth SOL/USD updates loaded by the test validator from tests/fixtures (see Anchor.toml)
+        const mint = await createFixedMint();
+        const [reserve, vault, receiptMint] = deriveReserve(mint, pool);
+        await program.methods
+            .addReserve(pythOracle(SOL_USD_FEED), reserveConfig(7000, 7500), NO_MINT_RISKS)
+            .accounts({
+                pool,
+                reserve,
+                mint,
+                reserveVault: vault,
+                receiptMint,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        const priceUpdates = [
+            // Price of zero
+            ["CwnQNEThiDK6ET8YjJRCm14RK6rVDek8sjqDuVhFEPso", "NonPositivePrice"],
+            // Published in 2020, far beyond the 60s max price age
+            ["Ga9Y4YZptkq5v3w3iK1kMNnXXk6GQecN3wY4XbdeCPbJ", "StalePrice"],
+            // $150 ± $15: 10% confidence against the pool's 2% limit
+            ["FkN8PDBDziatpWFwhWMgRPCPFgbQpeWCnp7QABpXtFMd", "PriceConfidenceTooWide"],
+        ];
+        for (const [priceUpdate, error] of priceUpdates) {
+            try {
+                await program.methods
+                    .refreshReserve()
+                    .accounts({
+                        pool,
+                        reserve,
+                        reserveVault: vault,
+                        oracle: new PublicKey(priceUpdate),
+                        secondaryOracle: null,
+                        stakePool: null,
+                    })
+                    .rpc();
+                assert.fail("Refresh from a bad price update should fail");
+            } catch (err) {
+                assert.include(err.toString(), error);
+            }
+        }
+    });
+
+    it("Prices an LST only through its SPL stake pool", async () => {
+        // JitoSOL-like: SOL/USD times the stake pool's lamports per pool token
+        const lstMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            9
+        );
+        const stakePool = Keypair.generate().publicKey;
+        const [reserve, vault, receiptMint] = deriveReserve(lstMint);
+        await program.methods
+            .addReserve(
+                {
+                    primary: { stakePool: { feedId: feedIdBytes(SOL_USD_FEED), stakePool } },
+                    secondary: null,
+                    maxDivergenceBps: 0,
+                },
+                reserveConfig(7000, 7500),
+                NO_MINT_RISKS
+            )
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve,
+                mint: lstMint,
+                reserveVault: vault,
+                receiptMint,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        // An account not owned by the stake pool program (here, the reserve vault) is refused
+        try {
+            await program.methods
+                .refreshReserve()
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve,
+                    reserveVault: vault,
+                    oracle: reserve, // Not reached: the stake pool is checked first
+                    secondaryOracle: null,
+                    stakePool: vault,
+                })
+                .rpc();
+            assert.fail("A foreign stake pool account should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidStakePool");
+        }
+    });
+
+    it("Updates pool config within bounds", async () => {
+        const poolConfig = {
+            maxPriceAgeSecs: new BN(60),
+            maxConfidenceBps: 150,
+            closeFactorBps: 5000,
+            fullLiquidationHfBps: 9500,
+            dustDebtValue: new BN(10_000_000),
+            maxLiquidationBonusBps: 1500,
+        };
+
+        await program.methods
+            .updatePoolConfig(poolConfig)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        assert.equal(poolAccount.maxPriceAgeSecs.toNumber(), 60);
+        assert.equal(poolAccount.maxConfidenceBps, 150);
+
+        try {
+            await program.methods
+                .updatePoolConfig({ ...poolConfig, closeFactorBps: 0 })
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    authority: provider.wallet.publicKey,
+                })
+                .rpc();
+            assert.fail("Zero close factor should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidPoolConfig");
+        }
+
+        // A 20% bonus cap would let the debt reserve (85% threshold) seize 102% of what it clears
+        const { reserves } = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        try {
+            await program.methods
+                .updatePoolConfig({ ...poolConfig, maxLiquidationBonusBps: 2000 })
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    authority: provider.wallet.publicKey,
+                })
+                .remainingAccounts(reserves.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+                .rpc();
+            assert.fail("Bonus cap that makes a reserve insolvent should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidPoolConfig");
+        }
+    });
+
+    it("Rejects a reserve config whose bonus makes liquidation insolvent", async () => {
+        const updateDebtReserveConfig = (config: ReturnType<typeof reserveConfig>) =>
+            program.methods
+                .updateReserveConfig(config)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve: debtReserve,
+                    reserveVault: debtReserveVault,
+                    authority: provider.wallet.publicKey,
+                })
+                .rpc();
+
+        // 98% threshold × 1.05 base bonus seizes more than the debt it clears
+        try {
+            await updateDebtReserveConfig(reserveConfig(9000, 9800));
+            assert.fail("Insolvent bonus should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidReserveConfig");
+        }
+
+        // 90% × 1.05 passes on the base bonus, but the dynamic bonus can reach the 15% cap: 90% × 1.15
+        try {
+            await updateDebtReserveConfig(reserveConfig(8500, 9000));
+            assert.fail("Bonus insolvent at the pool cap should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidReserveConfig");
+        }
+    });
+
+    it("Rotates a reserve's feed id", async () => {
+        const accounts = {
+            pool: poolKeypair.publicKey,
+            reserve: debtReserve,
+            reserveVault: debtReserveVault,
+            authority: provider.wallet.publicKey,
+        };
+        const newFeed = "11".repeat(32);
+
+        await program.methods.updateReserveOracle(pythOracle(newFeed)).accounts(accounts).rpc();
+        let reserveAccount = await program.account.reserve.fetch(debtReserve);
+        assert.deepEqual(reserveAccount.oracle.primary.pyth.feedId, feedIdBytes(newFeed));
+        assert.equal(reserveAccount.lastUpdateSlot.toNumber(), 0);
+
+        await program.methods.updateReserveOracle(pythOracle(USDC_USD_FEED)).accounts(accounts).rpc();
+        reserveAccount = await program.account.reserve.fetch(debtReserve);
+        assert.deepEqual(reserveAccount.oracle.primary.pyth.feedId, feedIdBytes(USDC_USD_FEED));
+    });
+
+    it("Prices a fixed-price reserve and checks it against a secondary oracle", async () => {
+        const stableMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+        const [reserve, vault, receiptMint] = deriveReserve(stableMint);
+        const refresh = () =>
+            program.methods
+                .refreshReserve()
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve,
+                    reserveVault: vault,
+                    oracle: reserve, // Unused by fixed-price sources
+                    secondaryOracle: reserve,
+                    stakePool: null,
+                })
+                .rpc();
+
+        // $1.00 primary, $1.01 secondary, 2% tolerance
+        await program.methods
+            .addReserve(
+                {
+                    primary: fixedSource(100_000_000, -8),
+                    secondary: fixedSource(101, -2),
+                    maxDivergenceBps: 200,
+                },
+                reserveConfig(8000, 8500),
+                NO_MINT_RISKS
+            )
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve,
+                mint: stableMint,
+                reserveVault: vault,
+                receiptMint,
+                authority: provider.wallet.publicKey,
+                systemProgram: SystemProgram.programId,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        await refresh();
+        const reserveAccount = await program.account.reserve.fetch(reserve);
+        assert.equal(reserveAccount.price.toNumber(), 100_000_000);
+        assert.equal(reserveAccount.priceExponent, -8);
+        assert.ok(reserveAccount.lastUpdateSlot.toNumber() > 0);
+
+        // Secondary drifts to $1.05: the refresh must refuse to cache a price
+        await program.methods
+            .updateReserveOracle({
+                primary: fixedSource(100_000_000, -8),
+                secondary: fixedSource(105, -2),
+                maxDivergenceBps: 200,
+            })
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve,
+                reserveVault: vault,
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        try {
+            await refresh();
+            assert.fail("Diverging oracles should fail");
+        } catch (err) {
+            assert.include(err.toString(), "OracleDivergence");
+        }
+    });
+
+    it("Hands pool authority over in two steps", async () => {
+        const multisig = Keypair.generate();
 
-        assert.equal(poolAccount.liquidationThreshold, 8000);
-        assert.equal(poolAccount.liquidationBonusBps, 500);
+        await program.methods
+            .proposeAuthority(multisig.publicKey)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        // Proposing alone does not move authority
+        let poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        assert.ok(poolAccount.authority.equals(provider.wallet.publicKey));
+        assert.ok(poolAccount.pendingAuthority.equals(multisig.publicKey));
+
+        await program.methods
+            .acceptAuthority()
+            .accounts({
+                pool: poolKeypair.publicKey,
+                pendingAuthority: multisig.publicKey,
+            })
+            .signers([multisig])
+            .rpc();
+
+        poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        assert.ok(poolAccount.authority.equals(multisig.publicKey));
+        assert.ok(poolAccount.pendingAuthority.equals(PublicKey.default));
+
+        // Hand it back so the remaining tests keep using the provider wallet
+        await program.methods
+            .proposeAuthority(provider.wallet.publicKey)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                authority: multisig.publicKey,
+            })
+            .signers([multisig])
+            .rpc();
+        await program.methods
+            .acceptAuthority()
+            .accounts({
+                pool: poolKeypair.publicKey,
+                pendingAuthority: provider.wallet.publicKey,
+            })
+            .rpc();
+    });
+
+    it("Funds the debt reserve's insurance fund", async () => {
+        const insuranceAmount = 10_000_000_000; // 10k USDC
+        const funderDebtAccount = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            provider.wallet.publicKey,
+            Keypair.generate()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            funderDebtAccount,
+            provider.wallet.publicKey,
+            insuranceAmount
+        );
+
+        const vaultBefore = await getAccount(provider.connection, debtReserveVault);
+
+        await program.methods
+            .fundInsurance(new BN(insuranceAmount))
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: debtReserve,
+                reserveVault: debtReserveVault,
+                mint: debtMint,
+                funderToken: funderDebtAccount,
+                funder: provider.wallet.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        const reserveAccount = await program.account.reserve.fetch(debtReserve);
+        const vaultAfter = await getAccount(provider.connection, debtReserveVault);
+
+        // Insurance sits in the vault but is earmarked, not lendable
+        assert.equal(reserveAccount.insuranceFund.toNumber(), insuranceAmount);
+        assert.equal((vaultAfter.amount - vaultBefore.amount).toString(), insuranceAmount.toString());
+    });
+
+    it("Rejects a reserve whose LTV reaches its liquidation threshold", async () => {
+        const otherMint = await createMint(
+            provider.connection,
+            provider.wallet.payer,
+            provider.wallet.publicKey,
+            null,
+            6
+        );
+        const [reserve, vault, receiptMint] = deriveReserve(otherMint);
+
+        try {
+            await program.methods
+                .addReserve(pythOracle(USDC_USD_FEED), reserveConfig(8000, 8000), NO_MINT_RISKS)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve,
+                    mint: otherMint,
+                    reserveVault: vault,
+                    receiptMint,
+                    authority: provider.wallet.publicKey,
+                    systemProgram: SystemProgram.programId,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .rpc();
+            assert.fail("Reserve with LTV >= threshold should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidMaxLtv");
+        }
     });
 
     it("Initializes user account", async () => {
@@ -110,68 +1347,975 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
 
         console.log("User account initialized at:", userAccountPDA.toBase58());
         console.log("Owner:", userAccount.owner.toBase58());
-        assert.equal(userAccount.collateralAmount.toNumber(), 0);
-        assert.equal(userAccount.debtAmount.toNumber(), 0);
+        assert.equal(userAccount.deposits.length, 0);
+        assert.equal(userAccount.borrows.length, 0);
     });
 
-    it("Deposits collateral and borrows", async () => {
+    it("Deposits collateral", async () => {
         console.log("\n=== Setting up Position ===");
 
         // Deposit collateral (e.g., 10 SOL worth in smallest units)
         const collateralAmount = new BN(10_000_000_000); // 10 SOL in lamports
-        await program.methods
+        const signature = await program.methods
             .depositCollateral(collateralAmount)
             .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: collateralReserve,
+                reserveVault: collateralReserveVault,
+                mint: collateralMint,
                 userAccount: userAccountPDA,
+                userCollateral: borrowerCollateralAccount,
                 owner: borrowerKeypair.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
             })
             .signers([borrowerKeypair])
+            .rpc({ commitment: "confirmed" });
+
+        const userAccount = await program.account.userAccount.fetch(userAccountPDA);
+        console.log("Collateral deposited:", userAccount.deposits[0].amount.toString());
+        assert.equal(userAccount.deposits.length, 1);
+        assert.ok(userAccount.deposits[0].reserve.equals(collateralReserve));
+        assert.equal(userAccount.deposits[0].amount.toString(), collateralAmount.toString());
+
+        // Collateral is backed by real tokens held in the reserve vault
+        const vaultAccount = await getAccount(provider.connection, collateralReserveVault);
+        const reserveAccount = await program.account.reserve.fetch(collateralReserve);
+        assert.equal(vaultAccount.amount.toString(), collateralAmount.toString());
+        assert.equal(reserveAccount.totalCollateral.toString(), collateralAmount.toString());
+
+        const [event] = await fetchEvents(signature);
+        assert.equal(event.name, "deposited");
+        assert.ok(event.data.userAccount.equals(userAccountPDA));
+        assert.equal(event.data.amount.toString(), collateralAmount.toString());
+    });
+
+    it("Flash borrows and repays within one transaction", async () => {
+        // Holds the fee; the loan itself comes from the reserve
+        const flashUserAccount = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            provider.wallet.publicKey,
+            Keypair.generate()
+        );
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            debtMint,
+            flashUserAccount,
+            provider.wallet.publicKey,
+            1_000_000
+        );
+
+        const loanAmount = new BN(100_000_000); // 100 USDC
+        const accounts = {
+            pool: poolKeypair.publicKey,
+            reserve: debtReserve,
+            reserveVault: debtReserveVault,
+            mint: debtMint,
+            userToken: flashUserAccount,
+            user: provider.wallet.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        };
+        const vaultBefore = await getAccount(provider.connection, debtReserveVault);
+
+        await program.methods
+            .flashBorrow(loanAmount)
+            .accounts(accounts)
+            .postInstructions([
+                await program.methods.flashRepay(loanAmount).accounts(accounts).instruction(),
+            ])
             .rpc();
 
-        // Borrow (e.g., 1500 USDC worth)
-        const borrowAmount = new BN(1500_000_000); // 1500 USDC in 6 decimals
+        // 30 bps fee on 100 USDC stays in the vault
+        const vaultAfter = await getAccount(provider.connection, debtReserveVault);
+        assert.equal((vaultAfter.amount - vaultBefore.amount).toString(), "300000");
+        const reserveAccount = await program.account.reserve.fetch(debtReserve);
+        assert.equal(reserveAccount.flashLoanAmount.toNumber(), 0);
+
+        try {
+            await program.methods.flashBorrow(loanAmount).accounts(accounts).rpc();
+            assert.fail("Flash borrow without repay should fail");
+        } catch (err) {
+            assert.include(err.toString(), "FlashRepayMissing");
+        }
+    });
+
+    it("Lets the guardian pause deposits without touching other operations", async () => {
+        const guardian = Keypair.generate();
+        const unpaused = { deposits: false, borrows: false, withdrawals: false, liquidations: false };
+
+        const { guardian: oldGuardian } = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        const signature = await program.methods
+            .setGuardian(guardian.publicKey)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                authority: provider.wallet.publicKey,
+            })
+            .rpc({ commitment: "confirmed" });
+        const guardianUpdated = (await fetchEvents(signature)).find((event) => event.name === "guardianUpdated");
+        assert.ok(guardianUpdated.data.oldGuardian.equals(oldGuardian));
+        assert.ok(guardianUpdated.data.newGuardian.equals(guardian.publicKey));
+
         await program.methods
-            .borrow(borrowAmount)
+            .setPauseFlags({ ...unpaused, deposits: true })
             .accounts({
-                userAccount: userAccountPDA,
+                pool: poolKeypair.publicKey,
+                signer: guardian.publicKey,
+            })
+            .signers([guardian])
+            .rpc();
+
+        let poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        assert.isTrue(poolAccount.pauseFlags.deposits);
+        assert.isFalse(poolAccount.pauseFlags.borrows);
+
+        try {
+            await program.methods
+                .depositCollateral(new BN(1_000_000))
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve: collateralReserve,
+                    reserveVault: collateralReserveVault,
+                    mint: collateralMint,
+                    userAccount: userAccountPDA,
+                    userCollateral: borrowerCollateralAccount,
+                    owner: borrowerKeypair.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .signers([borrowerKeypair])
+                .rpc();
+            assert.fail("Deposit while paused should fail");
+        } catch (err) {
+            assert.include(err.toString(), "DepositsPaused");
+        }
+
+        // Anyone other than the authority or guardian is rejected
+        try {
+            await program.methods
+                .setPauseFlags(unpaused)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    signer: borrowerKeypair.publicKey,
+                })
+                .signers([borrowerKeypair])
+                .rpc();
+            assert.fail("Borrower should not be able to unpause");
+        } catch (err) {
+            assert.include(err.toString(), "Unauthorized");
+        }
+
+        await program.methods
+            .setPauseFlags(unpaused)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                signer: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        assert.isFalse(poolAccount.pauseFlags.deposits);
+    });
+
+    it("Borrows within the obligation's borrow limit", async () => {
+        // 10 of a $150 collateral at 75% LTV backs up to $1125 of a $1 stable
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 15000, reserveConfig(7500, 8000), pool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        await supplyFixedReserve(debt, 2_000_000_000);
+        const { obligation, debtToken, borrow } = await openFixedObligation(collateral, debt, { deposit: 10_000_000 });
+
+        await borrow(500_000_000);
+
+        // The reserve's first borrow is taken at an index of exactly 1.0, one share per token
+        const userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.borrows.length, 1);
+        assert.ok(userAccount.borrows[0].reserve.equals(debt.reserve));
+        assert.equal(userAccount.borrows[0].debtShares.toNumber(), 500_000_000);
+
+        // Debt is paid out of the debt reserve's vault
+        assert.equal((await tokenBalance(debtToken)).toNumber(), 500_000_000);
+        assert.equal((await tokenBalance(debt.vault)).toNumber(), 1_500_000_000);
+    });
+
+    it("Rejects a borrow above the borrow limit", async () => {
+        // 10 of a $150 collateral at 75% LTV backs exactly $1125 of a $1 stable
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 15000, reserveConfig(7500, 8000), pool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        await supplyFixedReserve(debt, 2_000_000_000);
+        const { obligation, borrow } = await openFixedObligation(collateral, debt, { deposit: 10_000_000 });
+
+        try {
+            await borrow(1_125_000_001);
+            assert.fail("Borrow above the borrow limit should fail");
+        } catch (err) {
+            assert.include(err.toString(), "MaxLtvExceeded");
+        }
+
+        // The limit itself can be borrowed
+        await borrow(1_125_000_000);
+        const userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.borrows[0].debtShares.toNumber(), 1_125_000_000);
+    });
+
+    it("Repays part of the debt, then all of it", async () => {
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 15000, reserveConfig(7500, 8000), pool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        await supplyFixedReserve(debt, 2_000_000_000);
+        const { obligation, debtToken, borrow } = await openFixedObligation(collateral, debt, { deposit: 10_000_000 });
+        await borrow(500_000_000);
+
+        const repay = (amount: number) =>
+            program.methods
+                .repay(new BN(amount))
+                .accounts({
+                    pool,
+                    reserve: debt.reserve,
+                    reserveVault: debt.vault,
+                    mint: debt.mint,
+                    userAccount: obligation,
+                    userDebt: debtToken,
+                    isolatedReserve: null,
+                    owner: provider.wallet.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .rpc();
+        const debtShares = async () => {
+            const borrowed = (await program.account.userAccount.fetch(obligation)).borrows.find((b) =>
+                b.reserve.equals(debt.reserve)
+            );
+            return borrowed ? borrowed.debtShares : new BN(0);
+        };
+
+        // Let interest move the borrow index off 1.0
+        await new Promise((resolve) => setTimeout(resolve, 2000));
+
+        // A partial repay burns the shares it covers at the accrued index, rounded down
+        const sharesBefore = await debtShares();
+        const vaultBefore = await tokenBalance(debt.vault);
+        await repay(200_000_000);
+        const { borrowIndex, totalDebtShares } = await program.account.reserve.fetch(debt.reserve);
+        const burned = sharesBefore.sub(await debtShares());
+        assert.ok(borrowIndex.gt(INDEX_PRECISION));
+        assert.equal(burned.toString(), new BN(200_000_000).mul(INDEX_PRECISION).div(borrowIndex).toString());
+        assert.equal(totalDebtShares.toString(), sharesBefore.sub(burned).toString());
+        assert.equal((await tokenBalance(debt.vault)).sub(vaultBefore).toNumber(), 200_000_000);
+
+        // Repaying more than is owed takes only the debt and clears the borrow entry
+        const remainingShares = await debtShares();
+        await mintTo(
+            provider.connection,
+            provider.wallet.payer,
+            debt.mint,
+            debtToken,
+            provider.wallet.publicKey,
+            10_000_000
+        );
+        const vaultBeforeFull = await tokenBalance(debt.vault);
+        await repay(1_000_000_000);
+        const reserveAfter = await program.account.reserve.fetch(debt.reserve);
+        const userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.borrows.length, 0);
+        assert.equal(reserveAfter.totalDebtShares.toNumber(), 0);
+        assert.equal(
+            (await tokenBalance(debt.vault)).sub(vaultBeforeFull).toString(),
+            debtFromShares(remainingShares, reserveAfter.borrowIndex).toString()
+        );
+    });
+
+    it("Redeems receipts for at least what was supplied", async () => {
+        const receipts = new BN(1_000_000);
+        const before = await getAccount(provider.connection, supplierDebtAccount);
+
+        await program.methods
+            .redeem(receipts)
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: debtReserve,
+                reserveVault: debtReserveVault,
+                mint: debtMint,
+                receiptMint: debtReceiptMint,
+                userToken: supplierDebtAccount,
+                userReceipt: supplierReceiptAccount,
+                owner: provider.wallet.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        // Any interest paid so far has only raised the value of a receipt
+        const after = await getAccount(provider.connection, supplierDebtAccount);
+        const reserveAccount = await program.account.reserve.fetch(debtReserve);
+        assert.ok(new BN((after.amount - before.amount).toString()).gte(receipts));
+        assert.equal(
+            reserveAccount.totalSupplyShares.toString(),
+            (POOL_LIQUIDITY - receipts.toNumber()).toString()
+        );
+        assert.ok(reserveAccount.supplyIndex.gte(new BN("1000000000000000000")));
+    });
+
+    it("Only lets the authority withdraw protocol fees", async () => {
+        try {
+            await program.methods
+                .withdrawProtocolFees(new BN(1))
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve: debtReserve,
+                    reserveVault: debtReserveVault,
+                    mint: debtMint,
+                    destination: borrowerDebtAccount,
+                    authority: borrowerKeypair.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .signers([borrowerKeypair])
+                .rpc();
+            assert.fail("Only the authority can withdraw protocol fees");
+        } catch (err) {
+            assert.include(err.toString(), "ConstraintHasOne");
+        }
+    });
+
+    it("Keeps withdrawals within the borrow limit, not just the liquidation threshold", async () => {
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        await supplyFixedReserve(debt, 1_000_000_000);
+
+        // $700 borrowed against 1000 of collateral at 80% LTV / 85% threshold
+        const { obligation, collateralToken, reserveAccounts, borrow } = await openFixedObligation(collateral, debt, {
+            deposit: 1_000_000_000,
+        });
+        await borrow(700_000_000);
+        const withdraw = async (amount: number) =>
+            program.methods
+                .withdrawCollateral(new BN(amount))
+                .accounts({
+                    pool,
+                    reserve: collateral.reserve,
+                    reserveVault: collateral.vault,
+                    mint: collateral.mint,
+                    userAccount: obligation,
+                    userCollateral: collateralToken,
+                    owner: provider.wallet.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                })
+                .remainingAccounts(reserveAccounts)
+                .preInstructions(await refreshFixedReserves([collateral, debt]))
+                .rpc();
+
+        // 900 left keeps a $720 borrow limit
+        await withdraw(100_000_000);
+
+        // 850 left would still be healthy ($722.50 liquidation limit) but only backs $680 at LTV
+        try {
+            await withdraw(50_000_000);
+            assert.fail("Withdrawal past the borrow limit should fail");
+        } catch (err) {
+            assert.include(err.toString(), "MaxLtvExceeded");
+        }
+    });
+
+    it("Confines an isolated asset to a capped borrow of its designated stable", async () => {
+        // A $1 stable, and a $2 risky asset that may only back up to 100 of it
+        const stableMint = await createFixedMint();
+        const [stableReserve] = deriveReserve(stableMint);
+        const riskyMint = await createFixedMint();
+        const [riskyReserve] = deriveReserve(riskyMint);
+
+        // An isolated asset cannot be its own borrow reserve
+        try {
+            await addFixedReserve(riskyMint, 200, {
+                ...reserveConfig(5000, 6000),
+                isolation: { borrowReserve: riskyReserve, maxIsolatedDebt: new BN(100_000_000) },
+            });
+            assert.fail("Isolated reserve borrowing itself should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidReserveConfig");
+        }
+
+        const risky = await addFixedReserve(riskyMint, 200, {
+            ...reserveConfig(5000, 6000),
+            isolation: { borrowReserve: stableReserve, maxIsolatedDebt: new BN(100_000_000) },
+        });
+        const stable = await addFixedReserve(stableMint, 100, reserveConfig(8000, 8500));
+        await supplyFixedReserve(stable, 1_000_000_000);
+
+        const isolated = await openFixedObligation(risky, stable, { owner: borrowerKeypair, isolated: true });
+
+        // The cross-margin obligation cannot hold the isolated asset
+        try {
+            await isolated.depositCollateral(200_000_000, userAccountPDA);
+            assert.fail("Isolated collateral in a cross obligation should fail");
+        } catch (err) {
+            assert.include(err.toString(), "IsolatedCollateral");
+        }
+
+        await isolated.depositCollateral(200_000_000);
+
+        // With deposits taken as isolated collateral, isolation can no longer be switched off or
+        // pointed at another borrow reserve
+        const updateRiskyIsolation = (isolation: { borrowReserve: PublicKey; maxIsolatedDebt: BN } | null) =>
+            program.methods
+                .updateReserveConfig({ ...reserveConfig(5000, 6000), isolation })
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    reserve: risky.reserve,
+                    reserveVault: risky.vault,
+                    authority: provider.wallet.publicKey,
+                })
+                .rpc();
+        try {
+            await updateRiskyIsolation(null);
+            assert.fail("Switching isolation off with deposits should fail");
+        } catch (err) {
+            assert.include(err.toString(), "IsolationModeInUse");
+        }
+        try {
+            await updateRiskyIsolation({ borrowReserve: debtReserve, maxIsolatedDebt: new BN(100_000_000) });
+            assert.fail("Changing the borrow reserve with deposits should fail");
+        } catch (err) {
+            assert.include(err.toString(), "IsolationModeInUse");
+        }
+
+        // $400 of collateral at 50% LTV allows $200, but the isolation ceiling is 100
+        try {
+            await isolated.borrow(150_000_000);
+            assert.fail("Borrow above the isolated debt ceiling should fail");
+        } catch (err) {
+            assert.include(err.toString(), "IsolatedDebtCeilingExceeded");
+        }
+
+        await isolated.borrow(50_000_000);
+        let riskyAccount = await program.account.reserve.fetch(risky.reserve);
+        assert.equal(riskyAccount.isolatedDebt.toString(), "50000000");
+
+        await program.methods
+            .repay(new BN(20_000_000))
+            .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: stable.reserve,
+                reserveVault: stable.vault,
+                mint: stable.mint,
+                userAccount: isolated.obligation,
+                userDebt: isolated.debtToken,
+                isolatedReserve: risky.reserve,
                 owner: borrowerKeypair.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
             })
             .signers([borrowerKeypair])
             .rpc();
+        riskyAccount = await program.account.reserve.fetch(risky.reserve);
+        assert.equal(riskyAccount.isolatedDebt.toString(), "30000000");
 
-        const userAccount = await program.account.userAccount.fetch(userAccountPDA);
-        console.log("Collateral deposited:", userAccount.collateralAmount.toString());
-        console.log("Debt borrowed:", userAccount.debtAmount.toString());
+        // The debt ceiling of the same borrow reserve can still be raised
+        await updateRiskyIsolation({ borrowReserve: stable.reserve, maxIsolatedDebt: new BN(200_000_000) });
+        riskyAccount = await program.account.reserve.fetch(risky.reserve);
+        assert.equal(riskyAccount.config.isolation.maxIsolatedDebt.toString(), "200000000");
+    });
+
+    it("Gives an obligation of correlated assets its e-mode category's leverage", async () => {
+        // Stables: 75% LTV on their own, 90% inside e-mode category 1
+        const stableCategory = { id: 1, ltvBps: 9000, liquidationThreshold: 9500, liquidationBonusBps: 200 };
+        const setStableCategory = () =>
+            program.methods
+                .setEmodeCategory(stableCategory)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    authority: provider.wallet.publicKey,
+                })
+                .rpc();
+
+        // At the default 15% bonus cap a 95% threshold would seize 109% of the debt cleared
+        try {
+            await setStableCategory();
+            assert.fail("Category insolvent at the pool's bonus cap should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidEModeCategory");
+        }
+
+        // With the cap lowered to 5%, 95% × 1.05 stays below 100%
+        const poolAccount = await program.account.lendingPool.fetch(poolKeypair.publicKey);
+        await program.methods
+            .updatePoolConfig({
+                maxPriceAgeSecs: poolAccount.maxPriceAgeSecs,
+                maxConfidenceBps: poolAccount.maxConfidenceBps,
+                closeFactorBps: poolAccount.closeFactorBps,
+                fullLiquidationHfBps: poolAccount.fullLiquidationHfBps,
+                dustDebtValue: poolAccount.dustDebtValue,
+                maxLiquidationBonusBps: 500,
+            })
+            .accounts({
+                pool: poolKeypair.publicKey,
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+        await setStableCategory();
+        const stableConfig = { ...reserveConfig(7500, 8000), emodeCategory: 1 };
+        const collateral = await addFixedReserve(await createFixedMint(), 100, stableConfig);
+        const debt = await addFixedReserve(await createFixedMint(), 100, stableConfig);
+        await supplyFixedReserve(debt, 1_000_000_000);
+
+        // The provider wallet opens its own obligation with 100 of the collateral stable
+        const { obligation, reserveAccounts, borrow } = await openFixedObligation(collateral, debt, {
+            deposit: 100_000_000,
+        });
+        const setEmode = async (category: number) =>
+            program.methods
+                .setObligationEmode(category)
+                .accounts({
+                    pool: poolKeypair.publicKey,
+                    userAccount: obligation,
+                    owner: provider.wallet.publicKey,
+                })
+                .remainingAccounts(reserveAccounts)
+                .preInstructions(await refreshFixedReserves([collateral, debt]))
+                .rpc();
 
-        assert.ok(userAccount.collateralAmount.gt(new BN(0)));
-        assert.ok(userAccount.debtAmount.gt(new BN(0)));
+        // $85 of debt is above the reserve LTV of 75%...
+        try {
+            await borrow(85_000_000);
+            assert.fail("Borrow above the reserve LTV should fail outside e-mode");
+        } catch (err) {
+            assert.include(err.toString(), "MaxLtvExceeded");
+        }
+
+        // ...but within the category's 90%
+        await setEmode(1);
+        await borrow(85_000_000);
+        const obligationAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(obligationAccount.emodeCategory, 1);
+
+        // Leaving e-mode would put the obligation over its borrow limit
+        try {
+            await setEmode(0);
+            assert.fail("Leaving e-mode above the reserve LTV should fail");
+        } catch (err) {
+            assert.include(err.toString(), "MaxLtvExceeded");
+        }
     });
 
-    it("Executes liquidation with mock Pyth oracle (simulated)", async () => {
-        console.log("\n=== Testing Liquidation Logic (Mock) ===");
-        console.log(
-            "NOTE: Skipping actual liquidation execution due to Pyth oracle requirements"
+    it("Refreshes the obligation and reads its health", async () => {
+        // 1000 of a $1 collateral at 80/85 backs 500 of an interest-free $1 stable, so the
+        // values stay exact from one slot to the next: limits of $800 and $850, health 1.7
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        const debt = await addFixedReserve(
+            await createFixedMint(),
+            100,
+            {
+                ...reserveConfig(8000, 8500),
+                interestRateModel: { ...INTEREST_RATE_MODEL, baseRateBps: 0, slope1Bps: 0, slope2Bps: 0 },
+            },
+            pool
         );
-        console.log("In production, this would:");
-        console.log("1. Fetch latest prices from Pyth Hermes API");
-        console.log("2. Create price update transaction");
-        console.log("3. Bundle with liquidation via SolFabric");
-        console.log("4. Execute atomically through Jito");
+        await supplyFixedReserve(debt, 1_000_000_000);
+        const { obligation, reserveAccounts, borrow } = await openFixedObligation(collateral, debt, {
+            deposit: 1_000_000_000,
+        });
+        await borrow(500_000_000);
 
-        // In a real test environment with Pyth mock oracle:
-        // 1. We would create a mock PriceUpdateV2 account
-        // 2. Simulate price drop that makes position unhealthy
-        // 3. Execute liquidation
+        const expected = {
+            collateralValue: 1_000_000_000,
+            borrowLimit: 800_000_000,
+            liquidationLimit: 850_000_000,
+            debtValue: 500_000_000,
+            healthFactor: 1_700_000,
+        };
+        const assertHealth = (health: Record<keyof typeof expected, BN>) => {
+            for (const field of Object.keys(expected) as (keyof typeof expected)[]) {
+                assert.equal(health[field].toString(), expected[field].toString(), field);
+            }
+        };
 
-        // For now, we verify the account state is set up correctly
-        const userAccount = await program.account.userAccount.fetch(userAccountPDA);
-        console.log("\nCurrent position state:");
-        console.log("- Collateral:", userAccount.collateralAmount.toString());
-        console.log("- Debt:", userAccount.debtAmount.toString());
-        console.log("- Pool:", userAccount.pool.toBase58());
+        // refresh_obligation stores the snapshot and emits it
+        const signature = await program.methods
+            .refreshObligation()
+            .accounts({ pool, userAccount: obligation })
+            .remainingAccounts(reserveAccounts)
+            .preInstructions(await refreshFixedReserves([collateral, debt]))
+            .rpc({ commitment: "confirmed" });
+        const { slot } = await provider.connection.getTransaction(signature, {
+            commitment: "confirmed",
+            maxSupportedTransactionVersion: 0,
+        });
+        const userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.collateralValue.toNumber(), expected.collateralValue);
+        assert.equal(userAccount.debtValue.toNumber(), expected.debtValue);
+        assert.equal(userAccount.healthFactor.toNumber(), expected.healthFactor);
+        assert.equal(userAccount.lastRefreshSlot.toNumber(), slot);
+
+        const refreshed = (await fetchEvents(signature)).find((event) => event.name === "obligationRefreshed").data;
+        assertHealth(refreshed.health);
+        assert.equal(refreshed.health.slot.toNumber(), slot);
 
-        assert.ok(userAccount.collateralAmount.gt(new BN(0)), "Collateral should be > 0");
-        assert.ok(userAccount.debtAmount.gt(new BN(0)), "Debt should be > 0");
+        // get_health writes nothing and hands the snapshot back through return data
+        const health = await program.methods
+            .getHealth()
+            .accounts({ pool, userAccount: obligation })
+            .remainingAccounts(reserveAccounts)
+            .preInstructions(await refreshFixedReserves([collateral, debt]))
+            .view();
+        assertHealth(health);
+        assert.isAtLeast(health.slot.toNumber(), slot);
+        assert.equal((await program.account.userAccount.fetch(obligation)).lastRefreshSlot.toNumber(), slot);
+    });
+
+    it("Liquidates up to the close factor while the obligation is only slightly unhealthy", async () => {
+        // $790 against 1000 of a collateral with an 85% threshold: at $0.90 the $765 liquidation
+        // limit leaves a health factor of about 0.97, above the 0.95 full-liquidation line
+        const { liquidate } = await openUnderwaterObligation(reserveConfig(8000, 8500), 1_000_000_000, 790_000_000, 90);
+
+        // Asking for the whole borrow repays only the 50% close factor of it
+        const { liquidated, before, after } = await liquidate(790_000_000);
+        const debtAmount = debtFromShares(before.debtShares, after.borrowIndex);
+        assert.ok(liquidated.healthFactor.gte(new BN(950_000)) && liquidated.healthFactor.lt(new BN(1_000_000)));
+        assert.equal(liquidated.debtRepaid.toString(), debtAmount.muln(5000).divn(10000).toString());
+        assert.ok(after.debtShares.gt(new BN(0)));
+
+        // The bonus is the 5% base plus the health factor's shortfall below 1.0
+        const shortfallBps = Math.floor((1_000_000 - liquidated.healthFactor.toNumber()) / 100);
+        assert.equal(liquidated.liquidationBonusBps, 500 + shortfallBps);
+
+        assertLiquidationTransfers(before, after, liquidated);
+        assertLiquidationSettlement(before, after, liquidated);
+    });
+
+    it("Closes a deeply unhealthy obligation in full with the bonus at the pool's cap", async () => {
+        // $640 against 1000 of a collateral with a 75% threshold: at $0.75 the health factor is
+        // about 0.88, below the 0.95 full-liquidation line
+        const { liquidate } = await openUnderwaterObligation(reserveConfig(6500, 7500), 1_000_000_000, 640_000_000, 75);
+
+        const { liquidated, before, after } = await liquidate(1_280_000_000);
+        assert.ok(liquidated.healthFactor.lt(new BN(950_000)));
+        assert.equal(
+            liquidated.debtRepaid.toString(),
+            debtFromShares(before.debtShares, after.borrowIndex).toString()
+        );
+        assert.ok(after.debtShares.isZero());
+
+        // 5% base plus a 12% shortfall stops at the pool's default 15% cap
+        assert.equal(liquidated.liquidationBonusBps, 1500);
+
+        // Collateral is left over, so none of the debt became bad debt
+        assert.ok(after.deposit.gt(new BN(0)));
+        assertLiquidationTransfers(before, after, liquidated);
+        assertLiquidationSettlement(before, after, liquidated);
+    });
+
+    it("Closes a dust-sized obligation in full above the full-liquidation health factor", async () => {
+        // The same ~0.97 health factor as the close-factor case, but on $7.90 of debt, under the $10 dust line
+        const { liquidate } = await openUnderwaterObligation(reserveConfig(8000, 8500), 10_000_000, 7_900_000, 90);
+
+        const { liquidated, before, after } = await liquidate(15_800_000);
+        assert.ok(liquidated.healthFactor.gte(new BN(950_000)));
+        assert.equal(
+            liquidated.debtRepaid.toString(),
+            debtFromShares(before.debtShares, after.borrowIndex).toString()
+        );
+        assert.ok(after.debtShares.isZero());
+        assertLiquidationTransfers(before, after, liquidated);
+        assertLiquidationSettlement(before, after, liquidated);
+    });
+
+    it("Writes off the debt left once liquidation exhausts the collateral", async () => {
+        // 1000 of a $1 collateral backs 400 of one stable and 300 of another
+        const pool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        const otherDebt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), pool);
+        await supplyFixedReserve(debt, 1_000_000_000);
+        await supplyFixedReserve(otherDebt, 1_000_000_000);
+
+        const owner = provider.wallet.publicKey;
+        const { obligation, debtToken, borrow } = await openFixedObligation(collateral, debt, { deposit: 1_000_000_000 });
+        await borrow(400_000_000);
+        const allReserves = [collateral, debt, otherDebt];
+        const reserveAccounts = allReserves.map((r) => ({ pubkey: r.reserve, isSigner: false, isWritable: false }));
+        const otherDebtToken = await createAccount(provider.connection, provider.wallet.payer, otherDebt.mint, owner);
+        await program.methods
+            .borrow(new BN(300_000_000))
+            .accounts({
+                pool,
+                reserve: otherDebt.reserve,
+                reserveVault: otherDebt.vault,
+                mint: otherDebt.mint,
+                userAccount: obligation,
+                userDebt: otherDebtToken,
+                isolatedReserve: null,
+                owner,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .remainingAccounts(reserveAccounts)
+            .preInstructions(await refreshFixedReserves(allReserves))
+            .rpc();
+
+        // 20 of insurance stands behind the first stable, funded from the owner's own debt account
+        const funderToken = debtToken;
+        await mintTo(provider.connection, provider.wallet.payer, debt.mint, funderToken, owner, 20_000_000);
+        await program.methods
+            .fundInsurance(new BN(20_000_000))
+            .accounts({
+                pool,
+                reserve: debt.reserve,
+                reserveVault: debt.vault,
+                mint: debt.mint,
+                funderToken,
+                funder: owner,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .rpc();
+
+        const writeOffOtherDebt = () =>
+            program.methods
+                .writeOffBadDebt()
+                .accounts({
+                    pool,
+                    userAccount: obligation,
+                    reserve: otherDebt.reserve,
+                    reserveVault: otherDebt.vault,
+                    isolatedReserve: null,
+                })
+                .rpc({ commitment: "confirmed" });
+
+        // Debt can't be written off while the obligation still holds collateral
+        try {
+            await writeOffOtherDebt();
+            assert.fail("Write-off with collateral left should fail");
+        } catch (err) {
+            assert.include(err.toString(), "ObligationHasCollateral");
+        }
+
+        // At $0.40 the collateral is worth $400: at the 15% bonus it covers only ~348 of the first
+        // stable's 400, so all of it is seized and the rest of that borrow is bad debt
+        await repriceFixedReserve(collateral, 40);
+        const liquidatorRepay = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debt.mint,
+            liquidatorKeypair.publicKey
+        );
+        const liquidatorWithdraw = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            collateral.mint,
+            liquidatorKeypair.publicKey
+        );
+        await mintTo(provider.connection, provider.wallet.payer, debt.mint, liquidatorRepay, owner, 400_000_000);
+
+        const sharesBefore = (await program.account.userAccount.fetch(obligation)).borrows.find((b) =>
+            b.reserve.equals(debt.reserve)
+        ).debtShares;
+        const debtBefore = await program.account.reserve.fetch(debt.reserve);
+        const signature = await program.methods
+            .executeLiquidation(new BN(400_000_000))
+            .accounts({
+                pool,
+                userAccount: obligation,
+                repayReserve: debt.reserve,
+                repayVault: debt.vault,
+                repayMint: debt.mint,
+                withdrawReserve: collateral.reserve,
+                withdrawVault: collateral.vault,
+                withdrawMint: collateral.mint,
+                liquidatorRepay,
+                liquidatorWithdraw,
+                liquidator: liquidatorKeypair.publicKey,
+                repayTokenProgram: TOKEN_PROGRAM_ID,
+                withdrawTokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .remainingAccounts(reserveAccounts)
+            .preInstructions(await refreshFixedReserves(allReserves))
+            .signers([liquidatorKeypair])
+            .rpc({ commitment: "confirmed" });
+
+        const events = await fetchEvents(signature);
+        const liquidated = events.find((event) => event.name === "liquidated").data;
+        const writtenOff = events.find((event) => event.name === "badDebtWrittenOff").data;
+        const debtAfter = await program.account.reserve.fetch(debt.reserve);
+        assert.equal(liquidated.collateralSeized.toString(), "1000000000");
+        // Repaid shares round down, so the write-off can come out one unit over the rest of the debt
+        const overshoot = liquidated.debtRepaid
+            .add(writtenOff.debtWrittenOff)
+            .sub(debtFromShares(sharesBefore, debtAfter.borrowIndex));
+        assert.ok(overshoot.gten(0) && overshoot.lten(1));
+
+        // The insurance fund absorbs the loss first and suppliers take the remainder
+        assert.equal(writtenOff.coveredByInsurance.toString(), "20000000");
+        assert.equal(debtAfter.insuranceFund.toString(), "0");
+        assert.equal(
+            writtenOff.socialized.toString(),
+            writtenOff.debtWrittenOff.sub(writtenOff.coveredByInsurance).toString()
+        );
+        assert.equal(writtenOff.supplyIndex.toString(), debtAfter.supplyIndex.toString());
+
+        // Every supply share loses socialized / shares, rounded up; the index also accrued this
+        // transaction's interest, so the drop is at most that
+        const lossPerShare = writtenOff.socialized
+            .mul(INDEX_PRECISION)
+            .add(debtAfter.totalSupplyShares.subn(1))
+            .div(debtAfter.totalSupplyShares);
+        const indexDrop = debtBefore.supplyIndex.sub(debtAfter.supplyIndex);
+        assert.ok(indexDrop.lte(lossPerShare));
+        assert.ok(indexDrop.gt(lossPerShare.muln(999).divn(1000)));
+
+        // With the collateral gone, the other stable's debt can be written off by anyone
+        let userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.deposits.length, 0);
+        assert.equal(userAccount.borrows.length, 1);
+        const otherShares = userAccount.borrows[0].debtShares;
+
+        const writeOff = await fetchEvents(await writeOffOtherDebt());
+        const otherWrittenOff = writeOff.find((event) => event.name === "badDebtWrittenOff").data;
+        const otherDebtAfter = await program.account.reserve.fetch(otherDebt.reserve);
+        assert.equal(
+            otherWrittenOff.debtWrittenOff.toString(),
+            debtFromShares(otherShares, otherDebtAfter.borrowIndex).toString()
+        );
+        assert.equal(otherWrittenOff.coveredByInsurance.toString(), "0");
+        assert.equal(otherWrittenOff.socialized.toString(), otherWrittenOff.debtWrittenOff.toString());
+
+        userAccount = await program.account.userAccount.fetch(obligation);
+        assert.equal(userAccount.borrows.length, 0);
+    });
+
+    it("Liquidates through the swap program and repays from the proceeds", async () => {
+        // The main pool is full, so this runs on a pool of its own with $1 fixed-price stables
+        const liquidationPool = await createFixedPool();
+        const collateral = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), liquidationPool);
+        const debt = await addFixedReserve(await createFixedMint(), 100, reserveConfig(8000, 8500), liquidationPool);
+        await supplyFixedReserve(debt, 1_000_000_000);
+
+        // The provider wallet deposits 1000 of the collateral and borrows 790 against it
+        const owner = provider.wallet.publicKey;
+        const { obligation, reserveAccounts, borrow } = await openFixedObligation(collateral, debt, {
+            deposit: 1_000_000_000,
+        });
+        await borrow(790_000_000);
+
+        // Collateral drops to $0.90: the $765 liquidation limit no longer covers $790 of debt
+        await repriceFixedReserve(collateral, 90);
+
+        // A 1M / 1M constant-product pool still trades the collateral near $1
+        const [swapPool] = PublicKey.findProgramAddressSync(
+            [Buffer.from("swap_pool"), collateral.mint.toBuffer(), debt.mint.toBuffer()],
+            mockSwap.programId
+        );
+        const [swapCollateralVault, swapDebtVault] = [collateral.mint, debt.mint].map(
+            (mint) =>
+                PublicKey.findProgramAddressSync(
+                    [Buffer.from("swap_vault"), swapPool.toBuffer(), mint.toBuffer()],
+                    mockSwap.programId
+                )[0]
+        );
+        await mockSwap.methods
+            .initializeSwapPool(30)
+            .accounts({
+                swapPool,
+                mintA: collateral.mint,
+                mintB: debt.mint,
+                vaultA: swapCollateralVault,
+                vaultB: swapDebtVault,
+                payer: owner,
+                tokenProgramA: TOKEN_PROGRAM_ID,
+                tokenProgramB: TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+            })
+            .rpc();
+        for (const [mint, vault] of [
+            [collateral.mint, swapCollateralVault],
+            [debt.mint, swapDebtVault],
+        ]) {
+            await mintTo(provider.connection, provider.wallet.payer, mint, vault, owner, 1_000_000_000_000);
+        }
+
+        // The liquidator starts with no debt tokens: the repayment comes out of the swap
+        const liquidatorCollateral = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            collateral.mint,
+            liquidatorKeypair.publicKey
+        );
+        const liquidatorDebt = await createAccount(
+            provider.connection,
+            provider.wallet.payer,
+            debt.mint,
+            liquidatorKeypair.publicKey
+        );
+        const liquidateAndSwap = async (minProfit: number) =>
+            program.methods
+                .liquidateAndSwap(new BN(395_000_000), new BN(minProfit))
+                .accounts({
+                    pool: liquidationPool,
+                    userAccount: obligation,
+                    repayReserve: debt.reserve,
+                    repayVault: debt.vault,
+                    repayMint: debt.mint,
+                    withdrawReserve: collateral.reserve,
+                    withdrawVault: collateral.vault,
+                    withdrawMint: collateral.mint,
+                    liquidatorRepay: liquidatorDebt,
+                    liquidatorWithdraw: liquidatorCollateral,
+                    liquidator: liquidatorKeypair.publicKey,
+                    repayTokenProgram: TOKEN_PROGRAM_ID,
+                    withdrawTokenProgram: TOKEN_PROGRAM_ID,
+                    swapProgram: mockSwap.programId,
+                    swapPool,
+                    swapCollateralVault,
+                    swapDebtVault,
+                })
+                .remainingAccounts(reserveAccounts)
+                .preInstructions([
+                    ComputeBudgetProgram.setComputeUnitLimit({ units: 400_000 }),
+                    ...(await refreshFixedReserves([collateral, debt])),
+                ])
+                .signers([liquidatorKeypair])
+                .rpc({ commitment: "confirmed" });
+
+        // Only the pool's configured swap program can be called
+        try {
+            await liquidateAndSwap(0);
+            assert.fail("Liquidation with an unconfigured swap program should fail");
+        } catch (err) {
+            assert.include(err.toString(), "InvalidSwapProgram");
+        }
+        const setSwapProgram = await program.methods
+            .setSwapProgram(mockSwap.programId)
+            .accounts({
+                pool: liquidationPool,
+                authority: owner,
+            })
+            .rpc({ commitment: "confirmed" });
+        const swapProgramUpdated = (await fetchEvents(setSwapProgram)).find(
+            (event) => event.name === "swapProgramUpdated"
+        );
+        assert.ok(swapProgramUpdated.data.oldSwapProgram.equals(PublicKey.default));
+        assert.ok(swapProgramUpdated.data.newSwapProgram.equals(mockSwap.programId));
+
+        // Repaying 395 seizes about 475 of collateral (8.16% bonus), which swaps for about 473 < 395 + 100
+        try {
+            await liquidateAndSwap(100_000_000);
+            assert.fail("Swap proceeds below the repayment plus min profit should fail");
+        } catch (err) {
+            assert.include(err.toString(), "SlippageExceeded");
+        }
+
+        const signature = await liquidateAndSwap(50_000_000);
+
+        const events = await fetchEvents(signature);
+        const liquidated = events.find((event) => event.name === "liquidated");
+        const swapped = events.find((event) => event.name === "collateralSwapped");
+        assert.equal(liquidated.data.debtRepaid.toString(), "395000000");
+        assert.equal(swapped.data.collateralIn.toString(), liquidated.data.collateralSeized.toString());
+        assert.ok(swapped.data.profit.gte(new BN(50_000_000)));
+
+        // The liquidator holds no collateral and keeps the profit in the debt asset
+        assert.equal((await getAccount(provider.connection, liquidatorCollateral)).amount, BigInt(0));
+        assert.equal(
+            (await getAccount(provider.connection, liquidatorDebt)).amount.toString(),
+            swapped.data.profit.toString()
+        );
     });
 
     it("Simulates bundle submission via SolFabric", async () => {
@@ -182,8 +2326,14 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
         const depositTx = await program.methods
             .depositCollateral(new BN(100))
             .accounts({
+                pool: poolKeypair.publicKey,
+                reserve: collateralReserve,
+                reserveVault: collateralReserveVault,
+                mint: collateralMint,
                 userAccount: userAccountPDA,
+                userCollateral: borrowerCollateralAccount,
                 owner: borrowerKeypair.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
             })
             .transaction();
 
@@ -234,12 +2384,13 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
 
 3. EXECUTE ATOMICALLY (On-chain via Jito)
    ├─> Tx 1: Update Pyth oracle price
-   ├─> Tx 2: Execute liquidation
-   │   ├─> Read prices from oracle
-   │   ├─> Calculate health factor
+   ├─> Tx 2: Refresh reserves + execute liquidation
+   │   ├─> Cache prices on each reserve (refresh_reserve)
+   │   ├─> Calculate health factor across all deposits/borrows
    │   ├─> Verify position is liquidatable
    │   ├─> Calculate collateral to seize (+ 5% bonus)
-   │   └─> Update account balances
+   │   ├─> Pull repaid debt tokens from the liquidator
+   │   └─> Send seized collateral to the liquidator
    └─> Tx 3: Jito tip for priority inclusion
 
 4. BENEFITS
@@ -249,8 +2400,8 @@ describe("SolFabric Integration - Atomliq Liquidation", () => {
    ✓ Sub-second latency via ShredStream
 
 Current Implementation Status:
-✓ Lending pool with configurable parameters
-✓ User account management (deposit/borrow)
+✓ Lending pool with multi-asset reserves
+✓ Obligation management (deposit/borrow/repay/withdraw)
 ✓ Health factor calculation with Pyth prices
 ✓ Liquidation logic with bonus incentive
 ✓ SolFabric SDK for bundle simulation